@@ -26,14 +26,11 @@ fi
 echo "[3/3] Packaging..."
 DIST_DIR="dist/openwrt-arm64"
 rm -rf "${DIST_DIR}"
-mkdir -p "${DIST_DIR}/client/www/pkg"
+mkdir -p "${DIST_DIR}"
 
+# The web client is built into the binary, so it is all there is to ship.
 cp "${BINARY}" "${DIST_DIR}/"
 aarch64-unknown-linux-musl-strip "${DIST_DIR}/${BINARY_NAME}"
-cp client/www/index.html "${DIST_DIR}/client/www/"
-cp client/www/style.css "${DIST_DIR}/client/www/"
-cp client/www/pkg/client_bg.wasm "${DIST_DIR}/client/www/pkg/"
-cp client/www/pkg/client.js "${DIST_DIR}/client/www/pkg/"
 
 BINARY_SIZE=$(du -h "${DIST_DIR}/${BINARY_NAME}" | cut -f1)
 TOTAL_SIZE=$(du -sh "${DIST_DIR}" | cut -f1)
@@ -45,6 +42,6 @@ echo "Package: ${DIST_DIR}/ (${TOTAL_SIZE})"
 echo ""
 echo "Deploy to OpenWrt:"
 echo "  scp -r ${DIST_DIR}/* root@<router-ip>:/opt/paopaotang/"
-echo "  ssh root@<router-ip> 'cd /opt/paopaotang && ./${BINARY_NAME}'"
+echo "  ssh root@<router-ip> '/opt/paopaotang/${BINARY_NAME}'"
 echo ""
 echo "Then open http://<router-ip>:3000 in your browser"
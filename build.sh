@@ -17,3 +17,7 @@ echo "To run the game:"
 echo "  cargo run --release -p server"
 echo ""
 echo "Then open http://localhost:3000 in two browser tabs"
+echo ""
+echo "The client is built into the server; to try client changes without"
+echo "rebuilding it, serve client/www directly:"
+echo "  cargo run --release -p server -- --static-dir client/www"
@@ -1,33 +1,70 @@
+use std::collections::VecDeque;
+
+use shared::constants::TICK_RATE_MS;
 use shared::map::Tile;
+use shared::physics;
 use shared::protocol::*;
+use shared::rules::Rules;
 
 use crate::input::InputState;
+use crate::interpolation::SnapshotBuffer;
+use crate::lobby::{Lobby, WaitingRoom};
 use crate::network::Network;
 use crate::renderer::Renderer;
 
+// How long an operator notice stays on screen.
+const NOTICE_MS: f64 = 6000.0;
+
 #[derive(PartialEq)]
 enum Phase {
     Connecting,
-    Waiting { count: usize, need: usize },
+    Lobby,
+    Waiting,
     Playing,
     GameOver { winner: Option<u8> },
 }
 
+// Inputs sent to the server but not yet acknowledged via `last_seq`.
+struct PendingInput {
+    seq: u32,
+    dx: i8,
+    dy: i8,
+}
+
 pub struct Game {
     renderer: Renderer,
     input: InputState,
     network: Network,
     phase: Phase,
+    name: String,
+    lobby: Lobby,
+    waiting: WaitingRoom,
     my_id: u8,
+    /// Watching a room without a player; `my_id` means nothing meanwhile.
+    spectating: bool,
+    /// How many seconds behind the game the spectator feed runs.
+    spectator_delay: u32,
+    spectators: Vec<String>,
+    /// Player highlighted while spectating or after being eliminated.
+    follow: Option<u8>,
+    room_name: String,
+    join_code: Option<String>,
     map: Vec<Vec<Tile>>,
+    rules: Rules,
     players: Vec<PlayerState>,
     bombs: Vec<BombState>,
     explosions: Vec<ExplosionState>,
     items: Vec<ItemState>,
     tick: u64,
-    last_dx: f64,
-    last_dy: f64,
     sent_join: bool,
+    input_seq: u32,
+    pending_inputs: VecDeque<PendingInput>,
+    predicted_pos: Option<(i32, i32)>,
+    snapshots: SnapshotBuffer,
+    last_frame_ms: f64,
+    step_accumulator_ms: f64,
+    /// The latest operator notice and when to stop showing it.
+    notice: Option<(String, f64)>,
 }
 
 impl Game {
@@ -37,43 +74,94 @@ impl Game {
             input: InputState::new(),
             network: Network::new(ws_url),
             phase: Phase::Connecting,
+            name: format!("Player{}", (js_sys::Math::random() * 999.0) as u32),
+            lobby: Lobby::new(Vec::new(), now_ms()),
+            waiting: WaitingRoom::new(),
             my_id: 0,
+            spectating: false,
+            spectator_delay: 0,
+            spectators: Vec::new(),
+            follow: None,
+            room_name: String::new(),
+            join_code: None,
             map: Vec::new(),
+            rules: Rules::default(),
             players: Vec::new(),
             bombs: Vec::new(),
             explosions: Vec::new(),
             items: Vec::new(),
             tick: 0,
-            last_dx: 0.0,
-            last_dy: 0.0,
             sent_join: false,
+            input_seq: 0,
+            pending_inputs: VecDeque::new(),
+            predicted_pos: None,
+            snapshots: SnapshotBuffer::new(),
+            last_frame_ms: now_ms(),
+            step_accumulator_ms: 0.0,
+            notice: None,
         }
     }
 
     pub fn update(&mut self) {
+        let now = now_ms();
+        let elapsed = now - self.last_frame_ms;
+        self.last_frame_ms = now;
+
+        // Without a session to resume, such as before a first `Welcome` or
+        // while spectating, the new socket has to join again.
+        if self.network.reconnected() && !self.network.has_session() {
+            self.phase = Phase::Connecting;
+            self.sent_join = false;
+        }
         self.process_network();
 
         match &self.phase {
             Phase::Connecting => {
-                if self.network.is_connected() && !self.sent_join {
-                    let name = format!("Player{}", (js_sys::Math::random() * 999.0) as u32);
-                    self.network.send(&ClientMsg::Join { name });
+                if self.network.is_connected() && !self.sent_join && !self.network.has_session() {
+                    self.network.send(&ClientMsg::Join {
+                        name: self.name.clone(),
+                    });
                     self.sent_join = true;
                 }
             }
-            Phase::Waiting { .. } => {}
+            Phase::Lobby => {
+                self.lobby
+                    .update(&self.input, &self.network, &self.name, now);
+            }
+            Phase::Waiting if self.spectating => {
+                if self.input.is_key_just_pressed("Escape") {
+                    self.network.send(&ClientMsg::LeaveRoom);
+                }
+            }
+            Phase::Waiting => {
+                self.waiting.update(&self.input, &self.network, self.my_id);
+            }
+            Phase::Playing if self.watching() => {
+                if self.input.is_key_just_pressed("f") || self.input.is_key_just_pressed("F") {
+                    self.follow_next();
+                }
+                if self.spectating && self.input.is_key_just_pressed("Escape") {
+                    self.network.send(&ClientMsg::LeaveRoom);
+                }
+            }
             Phase::Playing => {
-                let (dx, dy) = self.input.get_movement();
-                if dx != self.last_dx || dy != self.last_dy {
-                    self.network.send(&ClientMsg::Move { dx, dy });
-                    self.last_dx = dx;
-                    self.last_dy = dy;
+                // Step local movement at the server tick rate; cap the backlog so
+                // a throttled background tab doesn't flood the server on return.
+                self.step_accumulator_ms =
+                    (self.step_accumulator_ms + elapsed).min(TICK_RATE_MS as f64 * 4.0);
+                while self.step_accumulator_ms >= TICK_RATE_MS as f64 {
+                    self.step_accumulator_ms -= TICK_RATE_MS as f64;
+                    self.step_input();
                 }
                 if self.input.wants_bomb() {
                     self.network.send(&ClientMsg::PlaceBomb);
                 }
             }
-            Phase::GameOver { .. } => {}
+            Phase::GameOver { .. } => {
+                if self.input.is_key_just_pressed("Enter") {
+                    self.network.send(&ClientMsg::LeaveRoom);
+                }
+            }
         }
 
         self.input.clear_frame();
@@ -82,48 +170,140 @@ impl Game {
     pub fn render(&self) {
         match &self.phase {
             Phase::Connecting => {
-                self.renderer.draw_waiting(0, 2);
+                self.renderer.draw_connecting();
+            }
+            Phase::Lobby => {
+                self.renderer.draw_lobby(&self.lobby);
             }
-            Phase::Waiting { count, need } => {
-                self.renderer.draw_waiting(*count, *need);
+            Phase::Waiting => {
+                self.renderer.draw_waiting(
+                    &self.waiting,
+                    self.me(),
+                    &self.room_name,
+                    self.join_code.as_deref(),
+                    &self.spectators,
+                );
             }
             Phase::Playing => {
+                let (players, tick) = self.displayed_players();
                 self.renderer.clear();
                 self.renderer.draw_map(&self.map);
                 self.renderer.draw_items(&self.items);
-                self.renderer.draw_bombs(&self.bombs, self.tick);
+                self.renderer.draw_bombs(&self.bombs, tick);
                 self.renderer.draw_explosions(&self.explosions);
-                self.renderer.draw_players(&self.players);
-                self.renderer.draw_hud(&self.players, self.my_id);
+                self.renderer.draw_players(&players, tick);
+                if self.watching() {
+                    let followed = self
+                        .follow
+                        .and_then(|id| players.iter().find(|p| p.id == id));
+                    if let Some(p) = followed {
+                        self.renderer.draw_focus(p);
+                    }
+                    self.renderer.draw_spectator_banner(
+                        followed,
+                        self.spectating,
+                        self.spectator_delay,
+                    );
+                }
+                self.renderer
+                    .draw_hud(&self.players, self.me(), &self.spectators);
             }
             Phase::GameOver { winner } => {
                 self.renderer.clear();
                 self.renderer.draw_map(&self.map);
-                self.renderer.draw_players(&self.players);
+                self.renderer.draw_players(&self.players, self.tick);
                 self.renderer.draw_game_over(*winner, &self.players);
             }
         }
+        if let Some((message, until)) = &self.notice {
+            if now_ms() < *until {
+                self.renderer.draw_notice(message);
+            }
+        }
     }
 
     fn process_network(&mut self) {
         let messages = self.network.poll();
         for msg in messages {
             match msg {
-                ServerMsg::Welcome { player_id, .. } => {
+                ServerMsg::Welcome {
+                    player_id,
+                    room_name,
+                    join_code,
+                    ..
+                } => {
                     self.my_id = player_id;
+                    self.spectating = false;
+                    self.room_name = room_name;
+                    self.join_code = join_code;
+                }
+                ServerMsg::Spectating {
+                    room_name,
+                    delay_secs,
+                    ..
+                } => {
+                    self.spectating = true;
+                    self.spectator_delay = delay_secs;
+                    self.room_name = room_name;
+                    self.join_code = None;
+                }
+                ServerMsg::Spectators { names } => {
+                    self.spectators = names;
+                }
+                ServerMsg::RoomList { rooms } => {
+                    self.lobby.set_rooms(rooms);
+                    self.spectating = false;
+                    self.spectators.clear();
+                    self.phase = Phase::Lobby;
+                }
+                ServerMsg::LobbyError { message } => {
+                    self.lobby.error = Some(message);
+                }
+                ServerMsg::ResumeRejected => {
+                    // The old seat is gone; join a new game instead.
+                    self.phase = Phase::Connecting;
+                    self.sent_join = false;
                 }
                 ServerMsg::Waiting {
-                    player_count,
                     need,
+                    max_players,
+                    host,
+                    players,
+                    ..
                 } => {
-                    self.phase = Phase::Waiting {
-                        count: player_count,
-                        need,
-                    };
+                    self.waiting.players = players;
+                    self.waiting.host = host;
+                    self.waiting.need = need;
+                    self.waiting.max_players = max_players;
+                    self.waiting.selected = self
+                        .waiting
+                        .selected
+                        .min(self.waiting.players.len().saturating_sub(1));
+                    self.waiting.countdown = None;
+                    self.phase = Phase::Waiting;
+                }
+                ServerMsg::Countdown { seconds_left } => {
+                    self.waiting.countdown = Some(seconds_left);
                 }
-                ServerMsg::GameStart { map, players } => {
+                ServerMsg::Notice { message } => {
+                    self.notice = Some((message, now_ms() + NOTICE_MS));
+                }
+                ServerMsg::Kicked => {
+                    self.lobby.error = Some("You were removed from the room".to_string());
+                }
+                ServerMsg::GameStart {
+                    map,
+                    players,
+                    rules,
+                } => {
                     self.map = map;
+                    self.rules = rules;
                     self.players = players;
+                    self.pending_inputs.clear();
+                    self.predicted_pos = None;
+                    self.snapshots.clear();
+                    self.step_accumulator_ms = 0.0;
+                    self.follow = None;
                     self.phase = Phase::Playing;
                 }
                 ServerMsg::GameState {
@@ -134,17 +314,117 @@ impl Game {
                     map,
                     tick,
                 } => {
+                    self.snapshots.push(now_ms(), tick, players.clone());
                     self.players = players;
                     self.bombs = bombs;
                     self.explosions = explosions;
                     self.items = items;
                     self.map = map;
                     self.tick = tick;
+                    self.reconcile();
                 }
                 ServerMsg::GameOver { winner } => {
                     self.phase = Phase::GameOver { winner };
                 }
+                // Only sent to bot connections.
+                ServerMsg::Observation(_) => {}
             }
         }
     }
+
+    /// This client's player, unless it is only spectating.
+    fn me(&self) -> Option<u8> {
+        (!self.spectating).then_some(self.my_id)
+    }
+
+    /// Whether the client watches instead of playing: it is spectating, or
+    /// its player has been eliminated.
+    fn watching(&self) -> bool {
+        self.spectating || self.players.iter().any(|p| p.id == self.my_id && !p.alive)
+    }
+
+    // Cycles the followed player through those still alive, then back to
+    // nobody.
+    fn follow_next(&mut self) {
+        let me = self.me();
+        let ids: Vec<u8> = self
+            .players
+            .iter()
+            .filter(|p| p.alive && Some(p.id) != me)
+            .map(|p| p.id)
+            .collect();
+        self.follow = match self.follow.and_then(|id| ids.iter().position(|i| *i == id)) {
+            Some(i) => ids.get(i + 1).copied(),
+            None => ids.first().copied(),
+        };
+    }
+
+    /// Sends one tick of movement input and applies it to the predicted position.
+    fn step_input(&mut self) {
+        let (dx, dy) = self.input.get_movement();
+        self.input_seq += 1;
+        let seq = self.input_seq;
+        self.network.send(&ClientMsg::Move { dx, dy, seq });
+
+        let Some(me) = self.players.iter().find(|p| p.id == self.my_id && p.alive) else {
+            return;
+        };
+        let (x, y) = self.predicted_pos.unwrap_or((me.x, me.y));
+        let tolerance = self.rules.corner_tolerance;
+        self.predicted_pos = Some(physics::move_player(
+            &self.map, x, y, dx, dy, me.speed, tolerance,
+        ));
+        self.pending_inputs.push_back(PendingInput { seq, dx, dy });
+    }
+
+    /// Rebases the prediction on the server's authoritative position and
+    /// replays every input the server hasn't applied yet.
+    fn reconcile(&mut self) {
+        let Some(me) = self.players.iter().find(|p| p.id == self.my_id) else {
+            return;
+        };
+        if !me.alive {
+            self.pending_inputs.clear();
+            self.predicted_pos = None;
+            return;
+        }
+
+        while self
+            .pending_inputs
+            .front()
+            .is_some_and(|input| input.seq <= me.last_seq)
+        {
+            self.pending_inputs.pop_front();
+        }
+
+        let (mut x, mut y) = (me.x, me.y);
+        let tolerance = self.rules.corner_tolerance;
+        for input in &self.pending_inputs {
+            (x, y) = physics::move_player(&self.map, x, y, input.dx, input.dy, me.speed, tolerance);
+        }
+        self.predicted_pos = Some((x, y));
+    }
+
+    /// Remote players interpolated between snapshots and the local player at
+    /// its predicted position, plus the tick to animate with.
+    fn displayed_players(&self) -> (Vec<PlayerState>, u64) {
+        let (mut players, tick) = match self.snapshots.sample(now_ms()) {
+            Some(sample) => (sample.players, sample.tick.max(0.0) as u64),
+            None => (self.players.clone(), self.tick),
+        };
+        if let Some((x, y)) = self.predicted_pos {
+            if let Some(me) = players.iter_mut().find(|p| p.id == self.my_id) {
+                me.x = x;
+                me.y = y;
+            }
+        }
+        (players, tick)
+    }
+}
+
+/// Milliseconds on the page's monotonic clock, for frame and network timing.
+pub fn now_ms() -> f64 {
+    web_sys::window()
+        .and_then(|w| w.performance())
+        .map_or(0.0, |p| p.now())
 }
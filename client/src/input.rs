@@ -58,24 +58,29 @@ impl InputState {
         self.keys_just_pressed.borrow().contains(key)
     }
 
+    /// Keys pressed since the last frame, for text entry.
+    pub fn just_pressed_keys(&self) -> Vec<String> {
+        self.keys_just_pressed.borrow().iter().cloned().collect()
+    }
+
     pub fn clear_frame(&self) {
         self.keys_just_pressed.borrow_mut().clear();
     }
 
-    pub fn get_movement(&self) -> (f64, f64) {
-        let mut dx = 0.0;
-        let mut dy = 0.0;
+    pub fn get_movement(&self) -> (i8, i8) {
+        let mut dx = 0;
+        let mut dy = 0;
         if self.is_key_down("ArrowUp") || self.is_key_down("w") || self.is_key_down("W") {
-            dy -= 1.0;
+            dy -= 1;
         }
         if self.is_key_down("ArrowDown") || self.is_key_down("s") || self.is_key_down("S") {
-            dy += 1.0;
+            dy += 1;
         }
         if self.is_key_down("ArrowLeft") || self.is_key_down("a") || self.is_key_down("A") {
-            dx -= 1.0;
+            dx -= 1;
         }
         if self.is_key_down("ArrowRight") || self.is_key_down("d") || self.is_key_down("D") {
-            dx += 1.0;
+            dx += 1;
         }
         (dx, dy)
     }
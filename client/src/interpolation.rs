@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+
+use shared::constants::TICK_RATE_MS;
+use shared::protocol::PlayerState;
+
+// Render remote entities this far behind the newest server tick so there is
+// almost always a snapshot on either side of the render time.
+const INTERP_DELAY_MS: f64 = TICK_RATE_MS as f64;
+// When snapshots are late, keep extrapolating for at most this long, then hold.
+const MAX_EXTRAPOLATION_MS: f64 = TICK_RATE_MS as f64 * 2.0;
+const MAX_SNAPSHOTS: usize = 16;
+// How quickly the clock offset follows snapshots that arrive later than expected.
+const OFFSET_DRIFT: f64 = 0.05;
+
+struct Snapshot {
+    tick: u64,
+    players: Vec<PlayerState>,
+}
+
+/// Buffer of recent snapshots, sampled on the server's tick timeline.
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<Snapshot>,
+    // Local clock minus server clock (tick * TICK_RATE_MS), tracking the
+    // earliest arrivals so network jitter doesn't shift the timeline.
+    clock_offset: Option<f64>,
+}
+
+pub struct Sample {
+    pub players: Vec<PlayerState>,
+    /// Server tick at render time, including the fraction between ticks.
+    pub tick: f64,
+}
+
+impl SnapshotBuffer {
+    pub fn new() -> Self {
+        SnapshotBuffer {
+            snapshots: VecDeque::new(),
+            clock_offset: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.clock_offset = None;
+    }
+
+    pub fn push(&mut self, now_ms: f64, tick: u64, players: Vec<PlayerState>) {
+        if self.snapshots.back().is_some_and(|s| s.tick >= tick) {
+            return;
+        }
+
+        let offset = now_ms - tick as f64 * TICK_RATE_MS as f64;
+        self.clock_offset = Some(match self.clock_offset {
+            Some(current) if offset > current => current + (offset - current) * OFFSET_DRIFT,
+            _ => offset,
+        });
+
+        self.snapshots.push_back(Snapshot { tick, players });
+        while self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub fn sample(&self, now_ms: f64) -> Option<Sample> {
+        let offset = self.clock_offset?;
+        let newest = self.snapshots.back()?;
+        let render_tick = (now_ms - offset - INTERP_DELAY_MS) / TICK_RATE_MS as f64;
+
+        // Pick the pair of snapshots around the render tick, or the last two
+        // when the render tick has run past the newest one.
+        let (a, b) = match self
+            .snapshots
+            .iter()
+            .position(|s| s.tick as f64 > render_tick)
+        {
+            Some(0) => {
+                let first = &self.snapshots[0];
+                return Some(Sample {
+                    players: first.players.clone(),
+                    tick: first.tick as f64,
+                });
+            }
+            Some(i) => (&self.snapshots[i - 1], &self.snapshots[i]),
+            None if self.snapshots.len() >= 2 => {
+                (&self.snapshots[self.snapshots.len() - 2], newest)
+            }
+            None => {
+                return Some(Sample {
+                    players: newest.players.clone(),
+                    tick: newest.tick as f64,
+                });
+            }
+        };
+
+        let max_tick = newest.tick as f64 + MAX_EXTRAPOLATION_MS / TICK_RATE_MS as f64;
+        let render_tick = render_tick.min(max_tick);
+        let t = (render_tick - a.tick as f64) / (b.tick - a.tick) as f64;
+
+        let players = b
+            .players
+            .iter()
+            .map(|pb| {
+                let mut p = pb.clone();
+                if let Some(pa) = a.players.iter().find(|pa| pa.id == pb.id) {
+                    if pa.alive && pb.alive {
+                        p.x = pa.x + ((pb.x - pa.x) as f64 * t).round() as i32;
+                        p.y = pa.y + ((pb.y - pa.y) as f64 * t).round() as i32;
+                    }
+                }
+                p
+            })
+            .collect();
+
+        Some(Sample {
+            players,
+            tick: render_tick,
+        })
+    }
+}
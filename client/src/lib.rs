@@ -1,7 +1,10 @@
 mod game;
 mod input;
+mod interpolation;
+mod lobby;
 mod network;
 mod renderer;
+mod replay;
 mod sprites;
 
 use std::cell::RefCell;
@@ -10,6 +13,9 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
 use game::Game;
+use replay::ReplayViewer;
+
+type FrameCallback = Rc<RefCell<Option<Closure<dyn FnMut()>>>>;
 
 #[wasm_bindgen(start)]
 pub fn main() -> Result<(), JsValue> {
@@ -22,33 +28,54 @@ pub fn main() -> Result<(), JsValue> {
         .unwrap()
         .dyn_into::<web_sys::HtmlCanvasElement>()?;
 
+    let location = window.location();
+    // `/?replay=<id>` plays a saved game instead of connecting.
+    let search = location.search().unwrap_or_default();
+    let replay_id = search
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("replay="))
+        .map(str::to_string);
+
     canvas.set_width(shared::constants::CANVAS_WIDTH);
-    canvas.set_height(shared::constants::CANVAS_HEIGHT + 30);
+    if let Some(id) = replay_id {
+        canvas.set_height(shared::constants::CANVAS_HEIGHT + renderer::REPLAY_BAR_HEIGHT);
+        let viewer = Rc::new(RefCell::new(ReplayViewer::new(&canvas, &id)));
+        start_loop(move || {
+            let mut v = viewer.borrow_mut();
+            v.update();
+            v.render();
+        });
+        return Ok(());
+    }
+    canvas.set_height(shared::constants::CANVAS_HEIGHT + renderer::HUD_HEIGHT);
 
-    let location = window.location();
     let host = location.host().unwrap_or_else(|_| "localhost:3000".into());
     let protocol = location.protocol().unwrap_or_else(|_| "http:".into());
     let ws_protocol = if protocol == "https:" { "wss" } else { "ws" };
     let ws_url = format!("{}://{}/ws", ws_protocol, host);
 
     let game = Rc::new(RefCell::new(Game::new(&canvas, &ws_url)));
+    start_loop(move || {
+        let mut g = game.borrow_mut();
+        g.update();
+        g.render();
+    });
 
-    let f: Rc<RefCell<Option<Closure<dyn FnMut()>>>> = Rc::new(RefCell::new(None));
+    Ok(())
+}
+
+/// Calls `frame` on every animation frame.
+fn start_loop(mut frame: impl FnMut() + 'static) {
+    let f: FrameCallback = Rc::new(RefCell::new(None));
     let g = f.clone();
 
-    let game_loop = game.clone();
     *g.borrow_mut() = Some(Closure::new(move || {
-        {
-            let mut g = game_loop.borrow_mut();
-            g.update();
-            g.render();
-        }
+        frame();
         request_animation_frame(f.borrow().as_ref().unwrap());
     }));
 
     request_animation_frame(g.borrow().as_ref().unwrap());
-
-    Ok(())
 }
 
 fn request_animation_frame(f: &Closure<dyn FnMut()>) {
@@ -0,0 +1,188 @@
+use shared::protocol::*;
+
+use crate::input::InputState;
+use crate::network::Network;
+
+const REFRESH_INTERVAL_MS: f64 = 2000.0;
+const JOIN_CODE_MAX_LEN: usize = 8;
+
+/// Lobby screen state: the public room list and an optional join-code prompt.
+pub struct Lobby {
+    pub rooms: Vec<RoomInfo>,
+    pub selected: usize,
+    /// Characters typed so far while entering a join code.
+    pub code_entry: Option<String>,
+    pub error: Option<String>,
+    last_refresh_ms: f64,
+}
+
+impl Lobby {
+    pub fn new(rooms: Vec<RoomInfo>, now_ms: f64) -> Self {
+        Lobby {
+            rooms,
+            selected: 0,
+            code_entry: None,
+            error: None,
+            last_refresh_ms: now_ms,
+        }
+    }
+
+    pub fn set_rooms(&mut self, rooms: Vec<RoomInfo>) {
+        self.rooms = rooms;
+        self.selected = self.selected.min(self.rooms.len().saturating_sub(1));
+    }
+
+    pub fn update(
+        &mut self,
+        input: &InputState,
+        network: &Network,
+        player_name: &str,
+        now_ms: f64,
+    ) {
+        if now_ms - self.last_refresh_ms >= REFRESH_INTERVAL_MS {
+            self.last_refresh_ms = now_ms;
+            network.send(&ClientMsg::ListRooms);
+        }
+
+        if let Some(code) = &mut self.code_entry {
+            for key in input.just_pressed_keys() {
+                match key.as_str() {
+                    "Escape" => {
+                        self.code_entry = None;
+                        return;
+                    }
+                    "Backspace" => {
+                        code.pop();
+                    }
+                    "Enter" => {
+                        let code = code.clone();
+                        self.code_entry = None;
+                        network.send(&ClientMsg::JoinByCode { code });
+                        return;
+                    }
+                    k if k.len() == 1 && code.len() < JOIN_CODE_MAX_LEN => {
+                        let c = k.chars().next().unwrap();
+                        if c.is_ascii_alphanumeric() {
+                            code.push(c.to_ascii_uppercase());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            return;
+        }
+
+        if input.is_key_just_pressed("ArrowUp") {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        if input.is_key_just_pressed("ArrowDown") && self.selected + 1 < self.rooms.len() {
+            self.selected += 1;
+        }
+        if input.is_key_just_pressed("Enter") {
+            if let Some(room) = self.rooms.get(self.selected) {
+                network.send(&ClientMsg::JoinRoom {
+                    room_id: room.id.clone(),
+                });
+            }
+        }
+
+        let pressed =
+            |k: &str| input.is_key_just_pressed(k) || input.is_key_just_pressed(&k.to_uppercase());
+        if pressed("v") {
+            if let Some(room) = self.rooms.get(self.selected) {
+                network.send(&ClientMsg::Spectate {
+                    room_id: room.id.clone(),
+                });
+            }
+        }
+        if pressed("q") {
+            network.send(&ClientMsg::QuickJoin);
+        }
+        if pressed("c") || pressed("p") {
+            network.send(&ClientMsg::CreateRoom {
+                name: format!("{}'s room", player_name),
+                private: pressed("p"),
+                min_players: None,
+                max_players: None,
+                lockstep: false,
+                spectator_delay_secs: None,
+            });
+        }
+        if pressed("j") {
+            self.error = None;
+            self.code_entry = Some(String::new());
+        }
+        if pressed("r") {
+            self.last_refresh_ms = now_ms;
+            network.send(&ClientMsg::ListRooms);
+        }
+    }
+}
+
+/// Waiting-room state: the roster, ready flags and host controls.
+pub struct WaitingRoom {
+    pub players: Vec<RoomMember>,
+    pub host: Option<u8>,
+    pub need: usize,
+    pub max_players: usize,
+    /// Roster entry the host has selected for kicking.
+    pub selected: usize,
+    /// Seconds left in the start countdown, once it is running.
+    pub countdown: Option<u32>,
+}
+
+impl WaitingRoom {
+    pub fn new() -> Self {
+        WaitingRoom {
+            players: Vec::new(),
+            host: None,
+            need: 0,
+            max_players: 0,
+            selected: 0,
+            countdown: None,
+        }
+    }
+
+    pub fn is_ready(&self, player_id: u8) -> bool {
+        self.players.iter().any(|p| p.id == player_id && p.ready)
+    }
+
+    pub fn update(&mut self, input: &InputState, network: &Network, my_id: u8) {
+        if input.is_key_just_pressed("Escape") {
+            network.send(&ClientMsg::LeaveRoom);
+            return;
+        }
+        if input.is_key_just_pressed(" ") || input.is_key_just_pressed("r") {
+            network.send(&ClientMsg::SetReady {
+                ready: !self.is_ready(my_id),
+            });
+        }
+
+        if self.host != Some(my_id) {
+            return;
+        }
+        if input.is_key_just_pressed("ArrowUp") {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        if input.is_key_just_pressed("ArrowDown") && self.selected + 1 < self.players.len() {
+            self.selected += 1;
+        }
+        if input.is_key_just_pressed("Enter") {
+            network.send(&ClientMsg::StartGame);
+        }
+        if input.is_key_just_pressed("k") {
+            if let Some(p) = self.players.get(self.selected) {
+                network.send(&ClientMsg::Kick { player_id: p.id });
+            }
+        }
+        for (key, difficulty) in [
+            ("1", BotDifficulty::Easy),
+            ("2", BotDifficulty::Medium),
+            ("3", BotDifficulty::Hard),
+        ] {
+            if input.is_key_just_pressed(key) {
+                network.send(&ClientMsg::AddBot { difficulty });
+            }
+        }
+    }
+}
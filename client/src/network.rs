@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
@@ -7,74 +7,163 @@ use web_sys::WebSocket;
 
 use shared::protocol::{ClientMsg, ServerMsg};
 
+const RECONNECT_BASE_MS: i32 = 500;
+const RECONNECT_MAX_MS: i32 = 8000;
+
+struct Connection {
+    url: String,
+    ws: RefCell<Option<WebSocket>>,
+    messages: RefCell<VecDeque<ServerMsg>>,
+    connected: Cell<bool>,
+    // Set whenever a socket opens, until `Network::reconnected` takes it.
+    opened: Cell<bool>,
+    resume_token: RefCell<Option<String>>,
+    attempts: Cell<u32>,
+}
+
+/// WebSocket connection that reconnects with exponential backoff and resumes
+/// the session with the token from the last `Welcome`.
 pub struct Network {
-    ws: WebSocket,
-    messages: Rc<RefCell<VecDeque<ServerMsg>>>,
-    connected: Rc<RefCell<bool>>,
+    conn: Rc<Connection>,
 }
 
 impl Network {
     pub fn new(url: &str) -> Self {
-        let ws = WebSocket::new(url).expect("Failed to create WebSocket");
-        ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
-
-        let messages = Rc::new(RefCell::new(VecDeque::new()));
-        let connected = Rc::new(RefCell::new(false));
-
-        {
-            let connected = connected.clone();
-            let onopen = Closure::<dyn FnMut()>::new(move || {
-                *connected.borrow_mut() = true;
-                web_sys::console::log_1(&"WebSocket connected".into());
-            });
-            ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
-            onopen.forget();
-        }
-
-        {
-            let messages = messages.clone();
-            let onmessage =
-                Closure::<dyn FnMut(_)>::new(move |event: web_sys::MessageEvent| {
-                    if let Ok(text) = event.data().dyn_into::<js_sys::JsString>() {
-                        let s: String = text.into();
-                        if let Ok(msg) = serde_json::from_str::<ServerMsg>(&s) {
-                            messages.borrow_mut().push_back(msg);
-                        }
-                    }
-                });
-            ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
-            onmessage.forget();
-        }
+        let conn = Rc::new(Connection {
+            url: url.to_string(),
+            ws: RefCell::new(None),
+            messages: RefCell::new(VecDeque::new()),
+            connected: Cell::new(false),
+            opened: Cell::new(false),
+            resume_token: RefCell::new(None),
+            attempts: Cell::new(0),
+        });
+        connect(&conn);
+        Network { conn }
+    }
 
-        {
-            let connected = connected.clone();
-            let onclose = Closure::<dyn FnMut()>::new(move || {
-                *connected.borrow_mut() = false;
-                web_sys::console::log_1(&"WebSocket disconnected".into());
-            });
-            ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
-            onclose.forget();
-        }
+    pub fn is_connected(&self) -> bool {
+        self.conn.connected.get()
+    }
 
-        Network {
-            ws,
-            messages,
-            connected,
-        }
+    /// Whether the server has issued a token this connection can resume with.
+    pub fn has_session(&self) -> bool {
+        self.conn.resume_token.borrow().is_some()
     }
 
-    pub fn is_connected(&self) -> bool {
-        *self.connected.borrow()
+    /// Whether a socket has opened since the last call. The server knows
+    /// nothing of a new socket until it resumes a session or joins.
+    pub fn reconnected(&self) -> bool {
+        self.conn.opened.replace(false)
     }
 
     pub fn send(&self, msg: &ClientMsg) {
-        if let Ok(json) = serde_json::to_string(msg) {
-            let _ = self.ws.send_with_str(&json);
-        }
+        send(&self.conn, msg);
     }
 
     pub fn poll(&self) -> Vec<ServerMsg> {
-        let mut msgs = self.messages.borrow_mut();
+        let mut msgs = self.conn.messages.borrow_mut();
         msgs.drain(..).collect()
     }
 }
+
+fn send(conn: &Connection, msg: &ClientMsg) {
+    if let (Some(ws), Ok(json)) = (conn.ws.borrow().as_ref(), serde_json::to_string(msg)) {
+        let _ = ws.send_with_str(&json);
+    }
+}
+
+fn connect(conn: &Rc<Connection>) {
+    let ws = match WebSocket::new(&conn.url) {
+        Ok(ws) => ws,
+        Err(_) => {
+            schedule_reconnect(conn);
+            return;
+        }
+    };
+    ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
+
+    {
+        let conn = conn.clone();
+        let onopen = Closure::<dyn FnMut()>::new(move || {
+            conn.connected.set(true);
+            conn.opened.set(true);
+            conn.attempts.set(0);
+            web_sys::console::log_1(&"WebSocket connected".into());
+            let token = conn.resume_token.borrow().clone();
+            if let Some(token) = token {
+                send(&conn, &ClientMsg::Resume { token });
+            }
+        });
+        ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+        onopen.forget();
+    }
+
+    {
+        let conn = conn.clone();
+        let onmessage = Closure::<dyn FnMut(_)>::new(move |event: web_sys::MessageEvent| {
+            if let Ok(text) = event.data().dyn_into::<js_sys::JsString>() {
+                let s: String = text.into();
+                if let Ok(msg) = serde_json::from_str::<ServerMsg>(&s) {
+                    match &msg {
+                        ServerMsg::Welcome { resume_token, .. } => {
+                            *conn.resume_token.borrow_mut() = Some(resume_token.clone());
+                        }
+                        ServerMsg::ResumeRejected => {
+                            *conn.resume_token.borrow_mut() = None;
+                        }
+                        _ => {}
+                    }
+                    conn.messages.borrow_mut().push_back(msg);
+                }
+            }
+        });
+        ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+    }
+
+    {
+        let conn = conn.clone();
+        let onclose = Closure::<dyn FnMut()>::new(move || {
+            conn.connected.set(false);
+            web_sys::console::log_1(&"WebSocket disconnected".into());
+            schedule_reconnect(&conn);
+        });
+        ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+        onclose.forget();
+    }
+
+    *conn.ws.borrow_mut() = Some(ws);
+}
+
+fn schedule_reconnect(conn: &Rc<Connection>) {
+    let attempt = conn.attempts.get();
+    conn.attempts.set(attempt + 1);
+    let delay = RECONNECT_BASE_MS
+        .saturating_mul(1 << attempt.min(5))
+        .min(RECONNECT_MAX_MS);
+
+    let conn = conn.clone();
+    let retry = Closure::once_into_js(move || connect(&conn));
+    let _ = web_sys::window()
+        .unwrap()
+        .set_timeout_with_callback_and_timeout_and_arguments_0(retry.unchecked_ref(), delay);
+}
+
+/// Fetches a text resource from the server over HTTP.
+pub async fn fetch_text(url: &str) -> Result<String, String> {
+    let window = web_sys::window().ok_or("no window")?;
+    let response = wasm_bindgen_futures::JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(|e| format!("{:?}", e))?
+        .dyn_into::<web_sys::Response>()
+        .map_err(|_| "not a response".to_string())?;
+    if !response.ok() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    let text = response.text().map_err(|e| format!("{:?}", e))?;
+    let text = wasm_bindgen_futures::JsFuture::from(text)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
+    text.as_string().ok_or_else(|| "not text".to_string())
+}
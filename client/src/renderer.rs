@@ -1,11 +1,29 @@
 use shared::constants::*;
 use shared::map::Tile;
+use shared::physics;
 use shared::protocol::*;
 use wasm_bindgen::JsCast;
 use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
 
+use crate::lobby::{Lobby, WaitingRoom};
 use crate::sprites::SpriteSheet;
 
+/// Height of the player and spectator lines under the map.
+pub const HUD_HEIGHT: u32 = 48;
+/// Height of the replay controls under the map.
+pub const REPLAY_BAR_HEIGHT: u32 = 50;
+
+/// What the replay bar under the map shows.
+pub struct ReplayStatus<'a> {
+    pub tick: u64,
+    pub end: u64,
+    pub playing: bool,
+    pub speed: f64,
+    pub followed: Option<&'a PlayerState>,
+    /// Set once playback reaches the end of a game someone won.
+    pub winner: Option<&'a str>,
+}
+
 pub struct Renderer {
     ctx: CanvasRenderingContext2d,
     sprites: SpriteSheet,
@@ -27,12 +45,8 @@ impl Renderer {
     }
 
     pub fn clear(&self) {
-        self.ctx.clear_rect(
-            0.0,
-            0.0,
-            CANVAS_WIDTH as f64,
-            CANVAS_HEIGHT as f64,
-        );
+        self.ctx
+            .clear_rect(0.0, 0.0, CANVAS_WIDTH as f64, CANVAS_HEIGHT as f64);
     }
 
     pub fn draw_map(&self, map: &[Vec<Tile>]) {
@@ -98,19 +112,19 @@ impl Renderer {
         }
     }
 
-    pub fn draw_players(&self, players: &[PlayerState]) {
+    pub fn draw_players(&self, players: &[PlayerState], tick: u64) {
         for p in players {
             if !p.alive {
                 continue;
             }
+            // Blink while spawn protection lasts
+            let hidden = p.invulnerable && (tick / 3) % 2 == 1;
+            let px = physics::to_tiles(p.x) * TILE_SIZE as f64;
+            let py = physics::to_tiles(p.y) * TILE_SIZE as f64;
             let idx = p.color_index as usize;
-            if idx < self.sprites.players.len() {
+            if idx < self.sprites.players.len() && !hidden {
                 self.ctx
-                    .draw_image_with_html_canvas_element(
-                        &self.sprites.players[idx],
-                        p.x * TILE_SIZE as f64,
-                        p.y * TILE_SIZE as f64,
-                    )
+                    .draw_image_with_html_canvas_element(&self.sprites.players[idx], px, py)
                     .unwrap();
             }
 
@@ -118,66 +132,354 @@ impl Renderer {
             self.ctx.set_fill_style_str("#FFFFFF");
             self.ctx.set_font("bold 10px monospace");
             self.ctx.set_text_align("center");
-            let _ = self.ctx.fill_text(
-                &p.name,
-                p.x * TILE_SIZE as f64 + TILE_SIZE as f64 / 2.0,
-                p.y * TILE_SIZE as f64 - 2.0,
-            );
+            let _ = self
+                .ctx
+                .fill_text(&p.name, px + TILE_SIZE as f64 / 2.0, py - 2.0);
         }
     }
 
-    pub fn draw_hud(&self, players: &[PlayerState], my_id: u8) {
+    /// Lists the players, then on a second line whoever is spectating.
+    /// `my_id` is `None` for a spectator.
+    pub fn draw_hud(&self, players: &[PlayerState], my_id: Option<u8>, spectators: &[String]) {
         let y_base = CANVAS_HEIGHT as f64 + 5.0;
         self.ctx.set_font("12px monospace");
         self.ctx.set_text_align("left");
 
         for (i, p) in players.iter().enumerate() {
             let x = 10.0 + i as f64 * 130.0;
-            let color = PLAYER_COLORS
-                .get(p.color_index as usize)
-                .unwrap_or(&"#FFF");
+            let color = PLAYER_COLORS.get(p.color_index as usize).unwrap_or(&"#FFF");
             self.ctx.set_fill_style_str(color);
 
-            let marker = if p.id == my_id { " (YOU)" } else { "" };
+            let marker = if Some(p.id) == my_id { " (YOU)" } else { "" };
             let status = if p.alive { "" } else { " [X]" };
             let text = format!("{}{}{}", p.name, marker, status);
             let _ = self.ctx.fill_text(&text, x, y_base + 14.0);
         }
+
+        if !spectators.is_empty() {
+            self.ctx.set_fill_style_str("#AAAAAA");
+            let text = format!("观众({}): {}", spectators.len(), spectators.join(", "));
+            let _ = self.ctx.fill_text(&text, 10.0, y_base + 34.0);
+        }
     }
 
-    pub fn draw_waiting(&self, count: usize, need: usize) {
+    /// Banner for a spectator or an eliminated player, naming the player
+    /// they follow. `delay_secs` is how far behind a spectator's feed runs.
+    pub fn draw_spectator_banner(
+        &self,
+        followed: Option<&PlayerState>,
+        spectating: bool,
+        delay_secs: u32,
+    ) {
+        self.ctx.set_fill_style_str("rgba(0,0,0,0.5)");
+        self.ctx.fill_rect(0.0, 0.0, CANVAS_WIDTH as f64, 22.0);
+
+        let title = match (spectating, delay_secs) {
+            (false, _) => "你已出局".to_string(),
+            (true, 0) => "观战中".to_string(),
+            (true, secs) => format!("观战中 (延迟{}秒)", secs),
+        };
+        let view = match followed {
+            Some(p) => format!("视角: {}", p.name),
+            None => "视角: 全场".to_string(),
+        };
+        let leave = if spectating { " | Esc 离开" } else { "" };
+        self.ctx.set_fill_style_str("#FFFFFF");
+        self.ctx.set_font("12px monospace");
+        self.ctx.set_text_align("center");
+        let _ = self.ctx.fill_text(
+            &format!("{} | {} | F 切换视角{}", title, view, leave),
+            CANVAS_WIDTH as f64 / 2.0,
+            15.0,
+        );
+    }
+
+    /// Outlines the player a replay is following.
+    pub fn draw_focus(&self, p: &PlayerState) {
+        if !p.alive {
+            return;
+        }
+        let half = TILE_SIZE as f64 / 2.0;
+        let cx = physics::to_tiles(p.x) * TILE_SIZE as f64 + half;
+        let cy = physics::to_tiles(p.y) * TILE_SIZE as f64 + half;
+        self.ctx.set_stroke_style_str("#FFD700");
+        self.ctx.set_line_width(2.0);
+        self.ctx.begin_path();
+        let _ = self.ctx.arc(cx, cy, half + 2.0, 0.0, std::f64::consts::TAU);
+        self.ctx.stroke();
+    }
+
+    pub fn draw_replay_bar(&self, status: &ReplayStatus) {
+        let top = CANVAS_HEIGHT as f64;
+        let width = CANVAS_WIDTH as f64;
+        self.ctx.set_fill_style_str("#1a1a2e");
+        self.ctx
+            .fill_rect(0.0, top, width, REPLAY_BAR_HEIGHT as f64);
+
+        let progress = status.tick as f64 / status.end.max(1) as f64;
+        self.ctx.set_fill_style_str("#333344");
+        self.ctx.fill_rect(0.0, top, width, 4.0);
+        self.ctx.set_fill_style_str("#FFD700");
+        self.ctx.fill_rect(0.0, top, width * progress, 4.0);
+
+        let seconds = |ticks: u64| ticks * TICK_RATE_MS / 1000;
+        let state = if status.playing { "▶" } else { "❚❚" };
+        let mut text = format!(
+            "{} {}x  {}:{:02} / {}:{:02}  ({}/{})",
+            state,
+            status.speed,
+            seconds(status.tick) / 60,
+            seconds(status.tick) % 60,
+            seconds(status.end) / 60,
+            seconds(status.end) % 60,
+            status.tick,
+            status.end
+        );
+        if let Some(p) = status.followed {
+            text += &format!(
+                "  视角: {} 火力{} 泡泡{} 速度{}",
+                p.name, p.bomb_range, p.max_bombs, p.speed
+            );
+        }
+        if let Some(name) = status.winner {
+            text += &format!("  胜者: {}", name);
+        }
+        self.ctx.set_font("12px monospace");
+        self.ctx.set_text_align("left");
+        self.ctx.set_fill_style_str("#FFFFFF");
+        let _ = self.ctx.fill_text(&text, 8.0, top + 20.0);
+
+        self.ctx.set_fill_style_str("#AAAAAA");
+        self.ctx.set_font("11px monospace");
+        let _ = self.ctx.fill_text(
+            "空格 播放/暂停 | ←→ 逐帧 | ↑↓ 速度 | PgUp/PgDn 0-9 跳转 | F 视角 | Esc 退出",
+            8.0,
+            top + 40.0,
+        );
+    }
+
+    fn draw_backdrop(&self) {
         self.clear();
         self.ctx.set_fill_style_str("#1a1a2e");
-        self.ctx.fill_rect(0.0, 0.0, CANVAS_WIDTH as f64, CANVAS_HEIGHT as f64);
+        self.ctx
+            .fill_rect(0.0, 0.0, CANVAS_WIDTH as f64, CANVAS_HEIGHT as f64);
+    }
 
+    fn draw_title(&self, y: f64) {
         self.ctx.set_fill_style_str("#FFFFFF");
         self.ctx.set_font("bold 24px monospace");
         self.ctx.set_text_align("center");
+        let _ = self
+            .ctx
+            .fill_text("泡泡堂 Online", CANVAS_WIDTH as f64 / 2.0, y);
+    }
+
+    pub fn draw_connecting(&self) {
+        self.draw_message("连接中...");
+    }
+
+    pub fn draw_message(&self, text: &str) {
+        self.draw_backdrop();
+        self.draw_title(CANVAS_HEIGHT as f64 / 2.0 - 40.0);
+
+        self.ctx.set_font("16px monospace");
         let _ = self.ctx.fill_text(
-            "泡泡堂 Online",
+            text,
             CANVAS_WIDTH as f64 / 2.0,
-            CANVAS_HEIGHT as f64 / 2.0 - 40.0,
+            CANVAS_HEIGHT as f64 / 2.0 + 10.0,
         );
+    }
 
-        self.ctx.set_font("16px monospace");
+    pub fn draw_lobby(&self, lobby: &Lobby) {
+        self.draw_backdrop();
+        self.draw_title(40.0);
+
+        self.ctx.set_font("14px monospace");
+        self.ctx.set_text_align("left");
+        if lobby.rooms.is_empty() {
+            self.ctx.set_fill_style_str("#AAAAAA");
+            let _ = self.ctx.fill_text("暂无公开房间", 40.0, 90.0);
+        }
+        for (i, room) in lobby.rooms.iter().enumerate() {
+            let y = 90.0 + i as f64 * 22.0;
+            if y > CANVAS_HEIGHT as f64 - 110.0 {
+                break;
+            }
+            let selected = i == lobby.selected;
+            self.ctx
+                .set_fill_style_str(if selected { "#FFD700" } else { "#FFFFFF" });
+            let status = match room.status {
+                RoomStatus::Lobby => "等待中",
+                RoomStatus::Countdown => "即将开始",
+                RoomStatus::Playing => "游戏中",
+                RoomStatus::Intermission => "结算中",
+                RoomStatus::Closed => "已关闭",
+            };
+            let marker = if selected { ">" } else { " " };
+            let mut line = format!(
+                "{} {:<18} {}/{} {}",
+                marker, room.name, room.players, room.max_players, status
+            );
+            if room.spectators > 0 {
+                line += &format!(" 观众{}", room.spectators);
+            }
+            let _ = self.ctx.fill_text(&line, 30.0, y);
+        }
+
+        self.ctx.set_text_align("center");
+        if let Some(code) = &lobby.code_entry {
+            self.ctx.set_fill_style_str("#FFD700");
+            self.ctx.set_font("bold 16px monospace");
+            let _ = self.ctx.fill_text(
+                &format!("房间码: {}_", code),
+                CANVAS_WIDTH as f64 / 2.0,
+                CANVAS_HEIGHT as f64 - 80.0,
+            );
+        } else if let Some(error) = &lobby.error {
+            self.ctx.set_fill_style_str("#FF6666");
+            self.ctx.set_font("14px monospace");
+            let _ = self.ctx.fill_text(
+                error,
+                CANVAS_WIDTH as f64 / 2.0,
+                CANVAS_HEIGHT as f64 - 80.0,
+            );
+        }
+
+        self.ctx.set_fill_style_str("#AAAAAA");
+        self.ctx.set_font("12px monospace");
         let _ = self.ctx.fill_text(
-            &format!("等待玩家... {}/{}", count, need),
+            "↑↓ 选择 | Enter 加入 | V 观战 | Q 快速加入 | R 刷新",
             CANVAS_WIDTH as f64 / 2.0,
-            CANVAS_HEIGHT as f64 / 2.0 + 10.0,
+            CANVAS_HEIGHT as f64 - 45.0,
+        );
+        let _ = self.ctx.fill_text(
+            "C 创建房间 | P 创建私人房间 | J 输入房间码",
+            CANVAS_WIDTH as f64 / 2.0,
+            CANVAS_HEIGHT as f64 - 25.0,
+        );
+    }
+
+    pub fn draw_waiting(
+        &self,
+        room: &WaitingRoom,
+        my_id: Option<u8>,
+        room_name: &str,
+        join_code: Option<&str>,
+        spectators: &[String],
+    ) {
+        self.draw_backdrop();
+        self.draw_title(40.0);
+
+        self.ctx.set_font("14px monospace");
+        let room_text = match join_code {
+            Some(code) => format!("{} | 房间码: {}", room_name, code),
+            None => room_name.to_string(),
+        };
+        let _ = self
+            .ctx
+            .fill_text(&room_text, CANVAS_WIDTH as f64 / 2.0, 65.0);
+        let _ = self.ctx.fill_text(
+            &format!(
+                "玩家 {}/{} (至少 {} 人)",
+                room.players.len(),
+                room.max_players,
+                room.need
+            ),
+            CANVAS_WIDTH as f64 / 2.0,
+            85.0,
         );
 
+        let is_host = my_id.is_some() && room.host == my_id;
+        self.ctx.set_text_align("left");
+        for (i, p) in room.players.iter().enumerate() {
+            let y = 120.0 + i as f64 * 24.0;
+            let selected = is_host && i == room.selected;
+            self.ctx
+                .set_fill_style_str(if selected { "#FFD700" } else { "#FFFFFF" });
+            let marker = if selected { ">" } else { " " };
+            let role = if room.host == Some(p.id) {
+                "[房主]"
+            } else if let Some(difficulty) = p.bot {
+                match difficulty {
+                    BotDifficulty::Easy => "[电脑:简单]",
+                    BotDifficulty::Medium => "[电脑:普通]",
+                    BotDifficulty::Hard => "[电脑:困难]",
+                }
+            } else if p.ready {
+                "[准备]"
+            } else {
+                "[未准备]"
+            };
+            let you = if Some(p.id) == my_id { " (YOU)" } else { "" };
+            let _ = self.ctx.fill_text(
+                &format!("{} {:<16} {}{}", marker, p.name, role, you),
+                60.0,
+                y,
+            );
+        }
+        if !spectators.is_empty() {
+            let y = 132.0 + room.players.len() as f64 * 24.0;
+            self.ctx.set_fill_style_str("#AAAAAA");
+            let _ = self.ctx.fill_text(
+                &format!("观众({}): {}", spectators.len(), spectators.join(", ")),
+                60.0,
+                y,
+            );
+        }
+
+        self.ctx.set_text_align("center");
+        if let Some(seconds) = room.countdown {
+            self.ctx.set_fill_style_str("#FFD700");
+            self.ctx.set_font("bold 48px monospace");
+            let _ = self.ctx.fill_text(
+                &seconds.to_string(),
+                CANVAS_WIDTH as f64 / 2.0,
+                CANVAS_HEIGHT as f64 - 90.0,
+            );
+        }
+
         self.ctx.set_fill_style_str("#AAAAAA");
         self.ctx.set_font("12px monospace");
         let _ = self.ctx.fill_text(
             "方向键/WASD 移动 | 空格 放泡泡",
             CANVAS_WIDTH as f64 / 2.0,
-            CANVAS_HEIGHT as f64 / 2.0 + 50.0,
+            CANVAS_HEIGHT as f64 - 45.0,
+        );
+        let controls = if is_host {
+            "Enter 开始 | ↑↓ 选择 K 踢出 | 1/2/3 加电脑 | Esc 返回大厅"
+        } else if my_id.is_none() {
+            "观战中 | Esc 返回大厅"
+        } else {
+            "空格/R 准备 | Esc 返回大厅"
+        };
+        let _ = self.ctx.fill_text(
+            controls,
+            CANVAS_WIDTH as f64 / 2.0,
+            CANVAS_HEIGHT as f64 - 25.0,
+        );
+    }
+
+    /// A message from the server's operators, over whatever is on screen.
+    pub fn draw_notice(&self, message: &str) {
+        let top = CANVAS_HEIGHT as f64 / 2.0 - 100.0;
+        self.ctx.set_fill_style_str("rgba(0,0,0,0.8)");
+        self.ctx
+            .fill_rect(20.0, top, CANVAS_WIDTH as f64 - 40.0, 40.0);
+
+        self.ctx.set_fill_style_str("#FFD700");
+        self.ctx.set_font("bold 14px monospace");
+        self.ctx.set_text_align("center");
+        let _ = self.ctx.fill_text(
+            &format!("服务器公告: {}", message),
+            CANVAS_WIDTH as f64 / 2.0,
+            top + 25.0,
         );
     }
 
     pub fn draw_game_over(&self, winner: Option<u8>, players: &[PlayerState]) {
         self.ctx.set_fill_style_str("rgba(0,0,0,0.7)");
-        self.ctx.fill_rect(0.0, 0.0, CANVAS_WIDTH as f64, CANVAS_HEIGHT as f64);
+        self.ctx
+            .fill_rect(0.0, 0.0, CANVAS_WIDTH as f64, CANVAS_HEIGHT as f64);
 
         self.ctx.set_fill_style_str("#FFD700");
         self.ctx.set_font("bold 32px monospace");
@@ -206,5 +508,13 @@ impl Renderer {
             CANVAS_WIDTH as f64 / 2.0,
             CANVAS_HEIGHT as f64 / 2.0 + 10.0,
         );
+
+        self.ctx.set_fill_style_str("#AAAAAA");
+        self.ctx.set_font("12px monospace");
+        let _ = self.ctx.fill_text(
+            "Enter 返回大厅",
+            CANVAS_WIDTH as f64 / 2.0,
+            CANVAS_HEIGHT as f64 / 2.0 + 45.0,
+        );
     }
 }
@@ -0,0 +1,246 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use shared::constants::TICK_RATE_MS;
+use shared::protocol::ServerMsg;
+use sim::game::GameInstance;
+use sim::replay::Replay;
+
+use crate::game::now_ms;
+use crate::input::InputState;
+use crate::network;
+use crate::renderer::{Renderer, ReplayStatus};
+
+pub const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 2;
+// A copy of the game is kept every this many ticks so seeking backwards only
+// re-simulates from the nearest one.
+const KEYFRAME_INTERVAL: u64 = 100;
+// How far Page Up / Page Down jump: five seconds.
+const JUMP_TICKS: u64 = 100;
+
+enum Load {
+    Pending,
+    Failed(String),
+    Ready(Box<Playback>),
+}
+
+/// Plays a recorded game back by re-running the simulation locally.
+pub struct ReplayViewer {
+    renderer: Renderer,
+    input: InputState,
+    load: Rc<RefCell<Load>>,
+    last_frame_ms: f64,
+}
+
+struct Playback {
+    replay: Replay,
+    keyframes: Vec<GameInstance>,
+    game: GameInstance,
+    playing: bool,
+    speed: usize,
+    accumulator_ms: f64,
+    follow: Option<u8>,
+}
+
+impl ReplayViewer {
+    /// Starts downloading the replay `id` from the server.
+    pub fn new(canvas: &web_sys::HtmlCanvasElement, id: &str) -> Self {
+        let load = Rc::new(RefCell::new(Load::Pending));
+        let url = format!("/replays/{}", id);
+        let pending = load.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = network::fetch_text(&url)
+                .await
+                .and_then(|text| serde_json::from_str::<Replay>(&text).map_err(|e| e.to_string()));
+            *pending.borrow_mut() = match result {
+                Ok(replay) => Load::Ready(Box::new(Playback::new(replay))),
+                Err(e) => Load::Failed(e),
+            };
+        });
+        ReplayViewer {
+            renderer: Renderer::new(canvas),
+            input: InputState::new(),
+            load,
+            last_frame_ms: now_ms(),
+        }
+    }
+
+    pub fn update(&mut self) {
+        let now = now_ms();
+        let elapsed = now - self.last_frame_ms;
+        self.last_frame_ms = now;
+
+        if self.input.is_key_just_pressed("Escape") {
+            if let Some(window) = web_sys::window() {
+                let _ = window.location().set_href("/");
+            }
+        }
+        if let Load::Ready(playback) = &mut *self.load.borrow_mut() {
+            playback.update(&self.input, elapsed);
+        }
+        self.input.clear_frame();
+    }
+
+    pub fn render(&self) {
+        match &*self.load.borrow() {
+            Load::Pending => self.renderer.draw_message("录像加载中..."),
+            Load::Failed(e) => self.renderer.draw_message(&format!("录像加载失败: {}", e)),
+            Load::Ready(playback) => playback.render(&self.renderer),
+        }
+    }
+}
+
+impl Playback {
+    fn new(replay: Replay) -> Self {
+        let mut game = replay.initial_game();
+        let mut keyframes = vec![game.clone()];
+        for inputs in &replay.inputs {
+            game.step(inputs);
+            if game.tick.is_multiple_of(KEYFRAME_INTERVAL) {
+                keyframes.push(game.clone());
+            }
+        }
+        Playback {
+            game: keyframes[0].clone(),
+            replay,
+            keyframes,
+            playing: true,
+            speed: NORMAL_SPEED,
+            accumulator_ms: 0.0,
+            follow: None,
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.replay.inputs.len() as u64
+    }
+
+    fn step(&mut self) {
+        if let Some(inputs) = self.replay.inputs.get(self.game.tick as usize) {
+            self.game.step(inputs);
+        }
+    }
+
+    /// Shows the game as it was after `tick`.
+    fn seek(&mut self, tick: u64) {
+        let tick = tick.min(self.end());
+        if tick < self.game.tick || tick - self.game.tick > KEYFRAME_INTERVAL {
+            let keyframe = (tick / KEYFRAME_INTERVAL) as usize;
+            self.game = self.keyframes[keyframe.min(self.keyframes.len() - 1)].clone();
+        }
+        while self.game.tick < tick {
+            self.step();
+        }
+        self.accumulator_ms = 0.0;
+    }
+
+    fn update(&mut self, input: &InputState, elapsed: f64) {
+        let tick = self.game.tick;
+        if input.is_key_just_pressed(" ") {
+            self.playing = !self.playing;
+            if self.playing && tick >= self.end() {
+                self.seek(0);
+            }
+        }
+        if input.is_key_just_pressed("ArrowUp") {
+            self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+        }
+        if input.is_key_just_pressed("ArrowDown") {
+            self.speed = self.speed.saturating_sub(1);
+        }
+        if input.is_key_just_pressed("ArrowLeft") {
+            self.playing = false;
+            self.seek(tick.saturating_sub(1));
+        }
+        if input.is_key_just_pressed("ArrowRight") {
+            self.playing = false;
+            self.seek(tick + 1);
+        }
+        if input.is_key_just_pressed("PageUp") {
+            self.seek(tick.saturating_sub(JUMP_TICKS));
+        }
+        if input.is_key_just_pressed("PageDown") {
+            self.seek(tick + JUMP_TICKS);
+        }
+        if input.is_key_just_pressed("Home") {
+            self.seek(0);
+        }
+        if input.is_key_just_pressed("End") {
+            self.seek(self.end());
+        }
+        // Digit keys jump to that tenth of the game, like a video player.
+        for digit in 0..10 {
+            if input.is_key_just_pressed(&digit.to_string()) {
+                self.seek(self.end() * digit / 10);
+            }
+        }
+        if input.is_key_just_pressed("f") || input.is_key_just_pressed("F") {
+            self.follow_next();
+        }
+
+        if !self.playing {
+            return;
+        }
+        // Cap the backlog so a throttled background tab doesn't stall on return.
+        let tick_ms = TICK_RATE_MS as f64;
+        self.accumulator_ms =
+            (self.accumulator_ms + elapsed * SPEEDS[self.speed]).min(tick_ms * 32.0);
+        while self.accumulator_ms >= tick_ms {
+            self.accumulator_ms -= tick_ms;
+            self.step();
+        }
+        if self.game.tick >= self.end() {
+            self.playing = false;
+        }
+    }
+
+    // Cycles the followed player through the roster, then back to nobody.
+    fn follow_next(&mut self) {
+        let ids: Vec<u8> = self.replay.players.iter().map(|p| p.id).collect();
+        self.follow = match self.follow.and_then(|id| ids.iter().position(|i| *i == id)) {
+            Some(i) => ids.get(i + 1).copied(),
+            None => ids.first().copied(),
+        };
+    }
+
+    fn render(&self, renderer: &Renderer) {
+        let ServerMsg::GameState {
+            players,
+            bombs,
+            explosions,
+            items,
+            map,
+            tick,
+        } = self.game.get_state_msg()
+        else {
+            return;
+        };
+        let followed = self
+            .follow
+            .and_then(|id| players.iter().find(|p| p.id == id));
+
+        renderer.clear();
+        renderer.draw_map(&map);
+        renderer.draw_items(&items);
+        renderer.draw_bombs(&bombs, tick);
+        renderer.draw_explosions(&explosions);
+        renderer.draw_players(&players, tick);
+        if let Some(p) = followed {
+            renderer.draw_focus(p);
+        }
+        renderer.draw_replay_bar(&ReplayStatus {
+            tick,
+            end: self.end(),
+            playing: self.playing,
+            speed: SPEEDS[self.speed],
+            followed,
+            winner: self
+                .replay
+                .winner
+                .filter(|_| tick >= self.end())
+                .and_then(|id| players.iter().find(|p| p.id == id))
+                .map(|p| p.name.as_str()),
+        });
+    }
+}
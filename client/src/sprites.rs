@@ -29,6 +29,7 @@ fn draw_pixel(ctx: &CanvasRenderingContext2d, color: &str, px: u32, py: u32, sca
     fill_rect(ctx, color, px as f64 * s, py as f64 * s, s, s);
 }
 
+#[allow(dead_code)] // arm/end sprites are generated but not drawn yet
 pub struct SpriteSheet {
     pub ground: HtmlCanvasElement,
     pub hard_block: HtmlCanvasElement,
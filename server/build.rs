@@ -0,0 +1,148 @@
+//! Embeds the web client (`client/www`, wasm-pack output included) in the
+//! server binary. With the `compress-assets` feature, text and wasm files
+//! also get gzip and brotli copies, made once here instead of per request.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+// Worth compressing; images, sounds and fonts already are.
+const COMPRESSIBLE: [&str; 11] = [
+    "html",
+    "htm",
+    "css",
+    "js",
+    "mjs",
+    "json",
+    "map",
+    "wasm",
+    "svg",
+    "txt",
+    "webmanifest",
+];
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let www = manifest_dir.join("../client/www");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed={}", www.display());
+
+    let mut files = Vec::new();
+    collect(&www, "", &mut files);
+    files.sort();
+    if !files.iter().any(|(path, _)| path == "index.html") {
+        println!(
+            "cargo:warning=No client/www/index.html to embed; serve the client with --static-dir"
+        );
+    } else if !files.iter().any(|(path, _)| path.ends_with(".wasm")) {
+        println!(
+            "cargo:warning=No wasm in client/www/pkg to embed; run wasm-pack before building the server"
+        );
+    }
+
+    let mut code = String::from("pub static ASSETS: &[Asset] = &[\n");
+    for (i, (path, file)) in files.iter().enumerate() {
+        let body = fs::read(file).unwrap();
+        let etag = format!("{:016x}", fnv1a(&body));
+        let (gzip, br) = if compressible(path) {
+            let gz = out_dir.join(format!("asset{}.gz", i));
+            let br = out_dir.join(format!("asset{}.br", i));
+            (
+                compress(&body, &gz, gzip).then_some(gz),
+                compress(&body, &br, brotli).then_some(br),
+            )
+        } else {
+            (None, None)
+        };
+        let include = |file: Option<PathBuf>| match file {
+            Some(file) => format!("Some(include_bytes!({:?}))", file.display().to_string()),
+            None => "None".to_string(),
+        };
+        let _ = writeln!(
+            code,
+            "    Asset {{ path: {:?}, body: include_bytes!({:?}), gzip: {}, br: {}, etag: {:?} }},",
+            path,
+            file.display().to_string(),
+            include(gzip),
+            include(br),
+            etag
+        );
+    }
+    code.push_str("];\n");
+    fs::write(out_dir.join("assets.rs"), code).unwrap();
+}
+
+/// Every file under `dir` as (URL path, file), skipping dot files and any
+/// `.gz` or `.br` copies already lying around.
+fn collect(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || name.ends_with(".gz") || name.ends_with(".br") {
+            continue;
+        }
+        let path = format!("{}{}", prefix, name);
+        let file = entry.path();
+        if file.is_dir() {
+            collect(&file, &format!("{}/", path), files);
+        } else {
+            files.push((path, file));
+        }
+    }
+}
+
+fn compressible(path: &str) -> bool {
+    let ext = path.rsplit('.').next().unwrap_or("");
+    COMPRESSIBLE.contains(&ext.to_ascii_lowercase().as_str())
+}
+
+/// Writes `body` compressed to `out`, unless that saves too little to bother.
+fn compress(body: &[u8], out: &Path, f: fn(&[u8]) -> Option<Vec<u8>>) -> bool {
+    match f(body) {
+        Some(packed) if packed.len() < body.len() * 9 / 10 => {
+            fs::write(out, packed).unwrap();
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(feature = "compress-assets")]
+fn gzip(body: &[u8]) -> Option<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(body).ok()?;
+    encoder.finish().ok()
+}
+
+#[cfg(feature = "compress-assets")]
+fn brotli(body: &[u8]) -> Option<Vec<u8>> {
+    let mut packed = Vec::new();
+    let params = brotli::enc::BrotliEncoderParams {
+        quality: 11,
+        lgwin: 22,
+        ..Default::default()
+    };
+    brotli::BrotliCompress(&mut &body[..], &mut packed, &params).ok()?;
+    Some(packed)
+}
+
+#[cfg(not(feature = "compress-assets"))]
+fn gzip(_: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(not(feature = "compress-assets"))]
+fn brotli(_: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+// A content hash for ETags, so they survive rebuilds of unchanged files.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,388 @@
+//! Operator controls, reachable as `/admin/<command>` HTTP endpoints with a
+//! bearer token and as commands typed on the server's stdin.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+
+use shared::protocol::ServerMsg;
+use shared::rules::Rules;
+
+use crate::api::{self, ApiRoom};
+use crate::connections::{ConnectionInfo, Connections, Role};
+use crate::http::Request;
+use crate::room_task::RoomCmd;
+use crate::Rooms;
+
+// Admin requests are small JSON commands.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+const CONSOLE_HELP: &str = "\
+commands:
+  rooms                      list rooms, private ones included
+  conns                      list connections
+  bans                       list banned addresses
+  kick <conn>                disconnect a connection, removing its player
+  ban <conn|ip>              ban an address and disconnect it
+  unban <ip>                 lift a ban
+  close <room>               send a room's players to the lobby and close it
+  say <message>              show a message to everyone connected
+  rules <room|all> <json>    rules for the next round, e.g. {\"corner_tolerance\":0}
+  shutdown                   tell everyone, close all connections and exit";
+
+/// One admin action. Over HTTP the path names the command and the JSON body
+/// carries the rest, e.g. `POST /admin/kick` with `{"connection": 3}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    Rooms,
+    Connections,
+    Bans,
+    Kick {
+        connection: u64,
+    },
+    /// Bans the address of `connection`, or `ip`.
+    Ban {
+        connection: Option<u64>,
+        ip: Option<IpAddr>,
+    },
+    Unban {
+        ip: IpAddr,
+    },
+    Close {
+        room_id: String,
+    },
+    Broadcast {
+        message: String,
+    },
+    /// Applies to `room_id`, or every open room if it is missing.
+    Rules {
+        room_id: Option<String>,
+        rules: Rules,
+    },
+    Shutdown,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Reply {
+    Rooms(Vec<AdminRoom>),
+    Connections(Vec<ConnectionInfo>),
+    Bans(Vec<IpAddr>),
+    Done { ok: String },
+}
+
+/// A room as admins see it, with the code of a private one.
+#[derive(Serialize)]
+pub struct AdminRoom {
+    #[serde(flatten)]
+    pub room: ApiRoom,
+    pub join_code: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Admin {
+    pub rooms: Rooms,
+    pub connections: Connections,
+    /// Required as `Authorization: Bearer <token>`; without one the HTTP
+    /// endpoints are off and only the console works.
+    token: Option<String>,
+    shutdown: Arc<Notify>,
+}
+
+impl Admin {
+    pub fn new(rooms: Rooms, connections: Connections, token: Option<String>) -> Self {
+        Admin {
+            rooms,
+            connections,
+            token: token.filter(|t| !t.is_empty()),
+            shutdown: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Resolves once an admin has asked the server to shut down.
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await;
+    }
+
+    pub async fn run(&self, command: Command) -> Result<Reply, String> {
+        match command {
+            Command::Rooms => {
+                let mut rooms: Vec<AdminRoom> = api::open_rooms(&self.rooms)
+                    .await
+                    .into_iter()
+                    .map(|(_, summary)| AdminRoom {
+                        join_code: summary.join_code.clone(),
+                        room: ApiRoom::from(summary),
+                    })
+                    .collect();
+                rooms.sort_by(|a, b| a.room.name.cmp(&b.room.name));
+                Ok(Reply::Rooms(rooms))
+            }
+            Command::Connections => Ok(Reply::Connections(self.connections.list())),
+            Command::Bans => Ok(Reply::Bans(self.connections.banned())),
+            Command::Kick { connection } => {
+                self.kick(connection, "You were disconnected by an administrator")
+                    .await?;
+                done(format!("Kicked connection {}", connection))
+            }
+            Command::Ban { connection, ip } => {
+                let ip = match (connection, ip) {
+                    (_, Some(ip)) => ip,
+                    (Some(id), None) => match self.connections.get(id) {
+                        Some((info, _)) => info.addr.ip(),
+                        None => return Err(format!("No connection {}", id)),
+                    },
+                    (None, None) => return Err("Give a connection or an ip".into()),
+                };
+                self.connections.ban(ip);
+                for id in self.connections.connected_from(ip) {
+                    let _ = self.kick(id, "You were banned by an administrator").await;
+                }
+                done(format!("Banned {}", ip))
+            }
+            Command::Unban { ip } => {
+                if !self.connections.unban(ip) {
+                    return Err(format!("{} is not banned", ip));
+                }
+                done(format!("Unbanned {}", ip))
+            }
+            Command::Close { room_id } => {
+                let room = self.rooms.lock().await.get(&room_id).cloned();
+                let Some(room) = room.filter(|room| !room.is_closed()) else {
+                    return Err(format!("No room {}", room_id));
+                };
+                let reason = "This room was closed by an administrator".to_string();
+                room.send(RoomCmd::Close { reason }).await;
+                done(format!("Closed room {}", room_id))
+            }
+            Command::Broadcast { message } => {
+                self.connections.send_all(&ServerMsg::Notice { message });
+                done(format!("Sent to {} connections", self.connections.count()))
+            }
+            Command::Rules { room_id, rules } => {
+                rules.validate()?;
+                let rooms: Vec<_> = match &room_id {
+                    Some(id) => match self.rooms.lock().await.get(id) {
+                        Some(room) => vec![room.clone()],
+                        None => return Err(format!("No room {}", id)),
+                    },
+                    None => self.rooms.lock().await.values().cloned().collect(),
+                };
+                for room in &rooms {
+                    room.send(RoomCmd::SetRules { rules }).await;
+                }
+                done(format!("Rules set in {} rooms", rooms.len()))
+            }
+            Command::Shutdown => {
+                self.shutdown.notify_one();
+                done("Shutdown requested".into())
+            }
+        }
+    }
+
+    /// Tells the connection why, takes its player out of their room so it
+    /// can't be resumed, and closes the socket.
+    async fn kick(&self, id: u64, reason: &str) -> Result<(), String> {
+        let (info, outbox) = self
+            .connections
+            .get(id)
+            .ok_or_else(|| format!("No connection {}", id))?;
+        if let (Some(room_id), Some(player_id), Role::Player | Role::Agent) =
+            (&info.room_id, info.player_id, info.role)
+        {
+            let room = self.rooms.lock().await.get(room_id).cloned();
+            if let Some(room) = room {
+                room.send(RoomCmd::Kick { player_id }).await;
+            }
+        }
+        outbox.send(&ServerMsg::Notice {
+            message: reason.to_string(),
+        });
+        outbox.close();
+        info!("Kicked connection {} ({})", id, info.addr);
+        Ok(())
+    }
+
+    /// Serves an `/admin/` request, reading its body off `stream`, and
+    /// returns the status line and JSON body to answer with.
+    pub async fn handle_http(
+        &self,
+        stream: &mut TcpStream,
+        request: &Request,
+    ) -> (&'static str, String) {
+        let Some(token) = &self.token else {
+            return error("404 Not Found", "Not Found");
+        };
+        let Some(body) = request.read_body(stream, MAX_BODY_BYTES).await else {
+            return error("400 Bad Request", "Malformed request");
+        };
+        let authorized = request
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+        if !authorized {
+            return error("401 Unauthorized", "Missing or wrong admin token");
+        }
+
+        // The path names the command; the body, if any, holds its fields.
+        let name = request
+            .path()
+            .trim_start_matches("/admin/")
+            .trim_end_matches('/');
+        let mut fields = if body.is_empty() {
+            serde_json::Map::new()
+        } else {
+            match serde_json::from_slice(&body) {
+                Ok(fields) => fields,
+                Err(e) => return error_msg("400 Bad Request", e.to_string()),
+            }
+        };
+        fields.insert("command".into(), name.into());
+        let command: Command = match serde_json::from_value(fields.into()) {
+            Ok(command) => command,
+            Err(e) => return error_msg("400 Bad Request", e.to_string()),
+        };
+        let reads = matches!(
+            command,
+            Command::Rooms | Command::Connections | Command::Bans
+        );
+        if !reads && request.method != "POST" {
+            return error(
+                "405 Method Not Allowed",
+                "Use POST for commands that change anything",
+            );
+        }
+        // Console commands echo their replies; log these for the record.
+        info!("Admin request {} from {:?}", name, stream.peer_addr().ok());
+        match self.run(command).await {
+            Ok(reply) => ("200 OK", serde_json::to_string(&reply).unwrap()),
+            Err(e) => error_msg("400 Bad Request", e),
+        }
+    }
+
+    /// Reads commands from stdin until it closes.
+    pub async fn run_console(self) {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match parse_console(line) {
+                Ok(command) => match self.run(command).await {
+                    Ok(reply) => print_reply(&reply),
+                    Err(e) => println!("error: {}", e),
+                },
+                Err(e) => println!("{}\n{}", e, CONSOLE_HELP),
+            }
+        }
+    }
+}
+
+fn done(ok: String) -> Result<Reply, String> {
+    Ok(Reply::Done { ok })
+}
+
+fn parse_console(line: &str) -> Result<Command, String> {
+    let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim();
+    let id = || {
+        rest.parse::<u64>()
+            .map_err(|_| format!("Not a connection id: {}", rest))
+    };
+    let ip = || {
+        rest.parse::<IpAddr>()
+            .map_err(|_| format!("Not an ip: {}", rest))
+    };
+    match word {
+        "rooms" => Ok(Command::Rooms),
+        "conns" | "connections" => Ok(Command::Connections),
+        "bans" => Ok(Command::Bans),
+        "kick" => Ok(Command::Kick { connection: id()? }),
+        "ban" => Ok(match rest.parse::<u64>() {
+            Ok(connection) => Command::Ban {
+                connection: Some(connection),
+                ip: None,
+            },
+            Err(_) => Command::Ban {
+                connection: None,
+                ip: Some(ip()?),
+            },
+        }),
+        "unban" => Ok(Command::Unban { ip: ip()? }),
+        "close" if !rest.is_empty() => Ok(Command::Close {
+            room_id: rest.to_string(),
+        }),
+        "say" if !rest.is_empty() => Ok(Command::Broadcast {
+            message: rest.to_string(),
+        }),
+        "rules" => {
+            let (room, json) = rest
+                .split_once(' ')
+                .ok_or("Give a room (or all) and rules")?;
+            let rules = serde_json::from_str(json).map_err(|e| format!("Bad rules: {}", e))?;
+            let room_id = (room != "all").then(|| room.to_string());
+            Ok(Command::Rules { room_id, rules })
+        }
+        "shutdown" => Ok(Command::Shutdown),
+        "help" => Err("".into()),
+        _ => Err(format!("Unknown command: {}", line)),
+    }
+}
+
+fn print_reply(reply: &Reply) {
+    match reply {
+        Reply::Rooms(rooms) => {
+            for r in rooms {
+                let code = r.join_code.as_deref().unwrap_or("-");
+                println!(
+                    "{} {:?} code={} players={}/{} spectators={} status={:?} tick={}",
+                    r.room.id,
+                    r.room.name,
+                    code,
+                    r.room.players,
+                    r.room.max_players,
+                    r.room.spectators,
+                    r.room.status,
+                    r.room.tick
+                );
+            }
+            println!("{} rooms", rooms.len());
+        }
+        Reply::Connections(connections) => {
+            for c in connections {
+                let room = c.room_id.as_deref().unwrap_or("-");
+                let name = c.name.as_deref().unwrap_or("-");
+                println!("{} {} {:?} {:?} room={}", c.id, c.addr, name, c.role, room);
+            }
+            println!("{} connections", connections.len());
+        }
+        Reply::Bans(bans) => {
+            for ip in bans {
+                println!("{}", ip);
+            }
+            println!("{} banned", bans.len());
+        }
+        Reply::Done { ok } => println!("{}", ok),
+    }
+}
+
+// Compares without stopping at the first difference, so response times
+// don't give the token away a byte at a time. Only its length can leak.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn error(status: &'static str, error: &str) -> (&'static str, String) {
+    error_msg(status, error.to_string())
+}
+
+fn error_msg(status: &'static str, error: String) -> (&'static str, String) {
+    (status, serde_json::json!({ "error": error }).to_string())
+}
@@ -0,0 +1,133 @@
+//! Read-only JSON endpoints under `/api/`, for dashboards, stream overlays
+//! and monitoring that would rather not speak WebSocket. Private rooms are
+//! left out, as they are from the lobby's room list.
+
+use serde::Serialize;
+
+use shared::protocol::RoomStatus;
+
+use crate::room_task::{RoomHandle, RoomSummary};
+use crate::Rooms;
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoomMode {
+    Normal,
+    /// Ticks wait for every external bot (see `ClientMsg::CreateRoom`).
+    Lockstep,
+}
+
+/// A room as listed at `/api/rooms`.
+#[derive(Serialize)]
+pub struct ApiRoom {
+    pub id: String,
+    pub name: String,
+    pub mode: RoomMode,
+    /// Seated players, bots included.
+    pub players: usize,
+    pub max_players: usize,
+    pub spectators: usize,
+    pub status: RoomStatus,
+    /// Tick of the current or last game.
+    pub tick: u64,
+}
+
+#[derive(Serialize)]
+pub struct Health {
+    pub status: &'static str,
+    /// Every open room, private ones included.
+    pub rooms: usize,
+    pub players: usize,
+    pub spectators: usize,
+}
+
+#[derive(Serialize)]
+struct ApiError {
+    error: &'static str,
+}
+
+/// Answers a `/api/` request with an HTTP status line and a JSON body.
+pub async fn handle(path: &str, rooms: &Rooms) -> (&'static str, String) {
+    match path {
+        "/api/health" => ok(&health(rooms).await),
+        "/api/rooms" | "/api/rooms/" => ok(&list(rooms).await),
+        _ => match path.strip_prefix("/api/rooms/") {
+            Some(id) => room_state(rooms, id).await,
+            None => error("404 Not Found", "Not Found"),
+        },
+    }
+}
+
+pub async fn open_rooms(rooms: &Rooms) -> Vec<(RoomHandle, RoomSummary)> {
+    rooms
+        .lock()
+        .await
+        .values()
+        .filter(|room| !room.is_closed())
+        .map(|room| (room.clone(), room.summary()))
+        .collect()
+}
+
+async fn health(rooms: &Rooms) -> Health {
+    let rooms = open_rooms(rooms).await;
+    Health {
+        status: "ok",
+        rooms: rooms.len(),
+        players: rooms.iter().map(|(_, s)| s.info.players).sum(),
+        spectators: rooms.iter().map(|(_, s)| s.info.spectators).sum(),
+    }
+}
+
+async fn list(rooms: &Rooms) -> Vec<ApiRoom> {
+    let mut list: Vec<ApiRoom> = open_rooms(rooms)
+        .await
+        .into_iter()
+        .filter(|(_, summary)| summary.join_code.is_none())
+        .map(|(_, summary)| ApiRoom::from(summary))
+        .collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    list
+}
+
+impl From<RoomSummary> for ApiRoom {
+    fn from(summary: RoomSummary) -> Self {
+        ApiRoom {
+            mode: if summary.lockstep {
+                RoomMode::Lockstep
+            } else {
+                RoomMode::Normal
+            },
+            tick: summary.tick,
+            id: summary.info.id,
+            name: summary.info.name,
+            players: summary.info.players,
+            max_players: summary.info.max_players,
+            spectators: summary.info.spectators,
+            status: summary.info.status,
+        }
+    }
+}
+
+// The room's state as spectators see it, so the spectator delay holds here
+// too.
+async fn room_state(rooms: &Rooms, id: &str) -> (&'static str, String) {
+    let room = rooms.lock().await.get(id).cloned();
+    let Some(room) = room.filter(|room| room.summary().join_code.is_none()) else {
+        return error("404 Not Found", "Room not found");
+    };
+    match room.state().await {
+        Some(json) => ("200 OK", json),
+        None => error(
+            "503 Service Unavailable",
+            "No state released yet; a delayed room only has one while spectated",
+        ),
+    }
+}
+
+fn ok(body: &impl Serialize) -> (&'static str, String) {
+    ("200 OK", serde_json::to_string(body).unwrap())
+}
+
+fn error(status: &'static str, error: &'static str) -> (&'static str, String) {
+    (status, serde_json::to_string(&ApiError { error }).unwrap())
+}
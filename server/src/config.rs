@@ -0,0 +1,301 @@
+//! Server settings. Built-in defaults are overridden by the TOML file given
+//! with `--config`, then by environment variables, then by command-line
+//! flags. See `config.example.toml` for every key.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::Parser;
+use log::LevelFilter;
+use serde::Deserialize;
+
+use shared::constants::{MAX_PLAYERS, MIN_PLAYERS};
+use shared::protocol::BotDifficulty;
+use shared::rules::Rules;
+
+use crate::room::{DEFAULT_MAX_SPECTATORS, DEFAULT_RECONNECT_GRACE, MAX_SPECTATOR_DELAY};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Addresses to accept connections on, e.g. `[::]:3000` for IPv6.
+    pub listen: Vec<SocketAddr>,
+    /// Serves the web client from this directory instead of the copy built
+    /// into the binary, e.g. `client/www` while working on it.
+    pub static_dir: Option<PathBuf>,
+    pub log_level: LevelFilter,
+    /// Enables `/admin/`; without one only the console can administer.
+    pub admin_token: Option<String>,
+    /// Logs every tick's inputs, for chasing desyncs.
+    pub log_inputs: bool,
+    pub rooms: RoomConfig,
+    /// Rules new rooms start with.
+    pub rules: Rules,
+    pub replays: ReplayConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomConfig {
+    /// Open rooms allowed at once; creating another is refused.
+    pub max_rooms: usize,
+    /// Player limits for rooms whose creator doesn't pick their own.
+    pub min_players: usize,
+    pub max_players: usize,
+    pub max_spectators: usize,
+    /// Rooms can override this when created.
+    pub spectator_delay_secs: u64,
+    /// How long a disconnected player's seat is held for a resume.
+    pub reconnect_grace_secs: u64,
+    /// A bot of this difficulty plays on for anyone who disconnects
+    /// mid-game; `None` leaves their character standing idle.
+    pub replace_disconnected: Option<BotDifficulty>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayConfig {
+    /// Where finished games are saved; empty, the default, turns recording
+    /// off. A relative path in the config file is taken from the file's
+    /// directory.
+    pub dir: PathBuf,
+    pub max_files: usize,
+    pub max_mb: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: vec![SocketAddr::from(([0, 0, 0, 0], 3000))],
+            static_dir: None,
+            log_level: LevelFilter::Info,
+            admin_token: None,
+            log_inputs: false,
+            rooms: RoomConfig::default(),
+            rules: Rules::default(),
+            replays: ReplayConfig::default(),
+        }
+    }
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        RoomConfig {
+            max_rooms: 64,
+            min_players: MIN_PLAYERS,
+            max_players: MAX_PLAYERS,
+            max_spectators: DEFAULT_MAX_SPECTATORS,
+            spectator_delay_secs: 0,
+            reconnect_grace_secs: DEFAULT_RECONNECT_GRACE.as_secs(),
+            replace_disconnected: None,
+        }
+    }
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        ReplayConfig {
+            dir: PathBuf::new(),
+            max_files: 200,
+            max_mb: 50,
+        }
+    }
+}
+
+impl RoomConfig {
+    pub fn spectator_delay(&self) -> Duration {
+        Duration::from_secs(self.spectator_delay_secs)
+    }
+
+    pub fn reconnect_grace(&self) -> Duration {
+        Duration::from_secs(self.reconnect_grace_secs)
+    }
+}
+
+/// Paopaotang game server.
+///
+/// Every option can also be set in the config file or, by the `PAOPAO_` name
+/// shown, in the environment. Flags win over the environment, which wins
+/// over the file.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// TOML config file.
+    #[arg(short, long, env = "PAOPAO_CONFIG")]
+    config: Option<PathBuf>,
+    /// Address to listen on, e.g. 0.0.0.0:3000 or [::]:3000. Repeat, or
+    /// separate with commas, for several.
+    #[arg(short, long, env = "PAOPAO_LISTEN", value_delimiter = ',')]
+    listen: Vec<SocketAddr>,
+    /// Serve the web client from this directory instead of the built-in copy.
+    #[arg(long, env = "PAOPAO_STATIC_DIR")]
+    static_dir: Option<PathBuf>,
+    /// off, error, warn, info, debug or trace.
+    #[arg(long, env = "PAOPAO_LOG_LEVEL")]
+    log_level: Option<LevelFilter>,
+    /// Token for the `/admin/` HTTP API.
+    #[arg(long, env = "PAOPAO_ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+    /// Log every tick's inputs.
+    #[arg(long, env = "PAOPAO_LOG_INPUTS")]
+    log_inputs: bool,
+    #[arg(long, env = "PAOPAO_MAX_ROOMS")]
+    max_rooms: Option<usize>,
+    /// Default minimum players to start a game.
+    #[arg(long, env = "PAOPAO_MIN_PLAYERS")]
+    min_players: Option<usize>,
+    /// Default seats per room.
+    #[arg(long, env = "PAOPAO_MAX_PLAYERS")]
+    max_players: Option<usize>,
+    #[arg(long, env = "PAOPAO_MAX_SPECTATORS")]
+    max_spectators: Option<usize>,
+    /// Default delay on spectator feeds.
+    #[arg(long, env = "PAOPAO_SPECTATOR_DELAY_SECS")]
+    spectator_delay_secs: Option<u64>,
+    #[arg(long, env = "PAOPAO_RECONNECT_GRACE_SECS")]
+    reconnect_grace_secs: Option<u64>,
+    /// Bot that takes over disconnected players mid-game: easy, medium or
+    /// hard. Off unless set.
+    #[arg(long, env = "PAOPAO_REPLACE_DISCONNECTED", value_parser = difficulty)]
+    replace_disconnected: Option<BotDifficulty>,
+    /// Default corner assist, in 1/256 of a tile; 0 turns it off.
+    #[arg(long, env = "PAOPAO_CORNER_TOLERANCE")]
+    corner_tolerance: Option<i32>,
+    /// Where replays are saved, from the working directory; empty turns
+    /// recording off.
+    #[arg(long, env = "PAOPAO_REPLAY_DIR", value_parser = empty_path)]
+    replay_dir: Option<PathBuf>,
+    #[arg(long, env = "PAOPAO_REPLAY_MAX_FILES")]
+    replay_max_files: Option<usize>,
+    #[arg(long, env = "PAOPAO_REPLAY_MAX_MB")]
+    replay_max_mb: Option<u64>,
+}
+
+impl Config {
+    /// Reads the command line, the file it names and the environment. The
+    /// error says what is wrong and where, ready to print.
+    pub fn load() -> Result<Config, String> {
+        let args = Args::parse();
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        args.apply(&mut config);
+        // Anything still relative came from a flag or the environment, so is
+        // taken from the working directory; pin it down before it's logged.
+        let dir = &mut config.replays.dir;
+        if !dir.as_os_str().is_empty() {
+            *dir = std::path::absolute(&*dir)
+                .map_err(|e| format!("Cannot resolve replay dir {}: {}", dir.display(), e))?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let mut config: Config =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = &mut config.replays.dir;
+        if !dir.as_os_str().is_empty() && dir.is_relative() {
+            let base = path.parent().unwrap_or(Path::new(""));
+            *dir = base.join(&*dir);
+        }
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.listen.is_empty() {
+            return Err("No listen address given".into());
+        }
+        let rooms = &self.rooms;
+        if rooms.max_rooms == 0 {
+            return Err("rooms.max_rooms must be at least 1".into());
+        }
+        if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&rooms.max_players) {
+            return Err(format!(
+                "rooms.max_players must be between {} and {}, not {}",
+                MIN_PLAYERS, MAX_PLAYERS, rooms.max_players
+            ));
+        }
+        if !(MIN_PLAYERS..=rooms.max_players).contains(&rooms.min_players) {
+            return Err(format!(
+                "rooms.min_players must be between {} and max_players ({}), not {}",
+                MIN_PLAYERS, rooms.max_players, rooms.min_players
+            ));
+        }
+        if rooms.spectator_delay() > MAX_SPECTATOR_DELAY {
+            return Err(format!(
+                "rooms.spectator_delay_secs can be at most {}",
+                MAX_SPECTATOR_DELAY.as_secs()
+            ));
+        }
+        self.rules.validate().map_err(|e| format!("rules.{}", e))?;
+        if let Some(dir) = &self.static_dir {
+            if !dir.is_dir() {
+                return Err(format!("static_dir {} is not a directory", dir.display()));
+            }
+        }
+        if self.admin_token.as_deref() == Some("") {
+            return Err("admin_token must not be empty".into());
+        }
+        Ok(())
+    }
+}
+
+impl Args {
+    fn apply(self, config: &mut Config) {
+        if !self.listen.is_empty() {
+            config.listen = self.listen;
+        }
+        if self.static_dir.is_some() {
+            config.static_dir = self.static_dir;
+        }
+        if let Some(level) = self.log_level {
+            config.log_level = level;
+        }
+        if self.admin_token.is_some() {
+            config.admin_token = self.admin_token;
+        }
+        config.log_inputs |= self.log_inputs;
+
+        let rooms = &mut config.rooms;
+        set(&mut rooms.max_rooms, self.max_rooms);
+        set(&mut rooms.min_players, self.min_players);
+        set(&mut rooms.max_players, self.max_players);
+        set(&mut rooms.max_spectators, self.max_spectators);
+        set(&mut rooms.spectator_delay_secs, self.spectator_delay_secs);
+        set(&mut rooms.reconnect_grace_secs, self.reconnect_grace_secs);
+        if self.replace_disconnected.is_some() {
+            rooms.replace_disconnected = self.replace_disconnected;
+        }
+        set(&mut config.rules.corner_tolerance, self.corner_tolerance);
+
+        let replays = &mut config.replays;
+        set(&mut replays.dir, self.replay_dir);
+        set(&mut replays.max_files, self.replay_max_files);
+        set(&mut replays.max_mb, self.replay_max_mb);
+    }
+}
+
+// Clap's own path parser refuses the empty value that turns replays off.
+fn empty_path(value: &str) -> Result<PathBuf, String> {
+    Ok(PathBuf::from(value))
+}
+
+fn difficulty(value: &str) -> Result<BotDifficulty, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "easy" => Ok(BotDifficulty::Easy),
+        "medium" => Ok(BotDifficulty::Medium),
+        "hard" => Ok(BotDifficulty::Hard),
+        _ => Err("expected easy, medium or hard".into()),
+    }
+}
+
+fn set<T>(setting: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *setting = value;
+    }
+}
@@ -0,0 +1,191 @@
+//! Every open WebSocket connection, so operators can list, message and close
+//! them, and the addresses they have banned.
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use shared::protocol::ServerMsg;
+
+use crate::outbox::Outbox;
+
+/// What a connection is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Browsing rooms, or not yet joined.
+    Lobby,
+    Player,
+    /// An external bot seated in a room.
+    Agent,
+    Spectator,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub addr: SocketAddr,
+    pub name: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub connected_at: u64,
+    pub role: Role,
+    pub room_id: Option<String>,
+    /// Set for players and external bots.
+    pub player_id: Option<u8>,
+}
+
+struct Entry {
+    info: ConnectionInfo,
+    outbox: Outbox,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    open: HashMap<u64, Entry>,
+    banned: HashSet<IpAddr>,
+}
+
+/// Cheap, cloneable handle to the registry. Its lock is never held across
+/// an await.
+#[derive(Clone, Default)]
+pub struct Connections {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Connections {
+    /// Adds a connection, which stays listed until the returned
+    /// `Registration` is dropped.
+    pub fn register(&self, addr: SocketAddr, outbox: Outbox) -> Registration {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        let connected_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let info = ConnectionInfo {
+            id,
+            addr,
+            name: None,
+            connected_at,
+            role: Role::Lobby,
+            room_id: None,
+            player_id: None,
+        };
+        inner.open.insert(id, Entry { info, outbox });
+        Registration {
+            id,
+            connections: self.clone(),
+        }
+    }
+
+    /// Open connections, oldest first.
+    pub fn list(&self) -> Vec<ConnectionInfo> {
+        let inner = self.inner.lock().unwrap();
+        let mut list: Vec<ConnectionInfo> = inner.open.values().map(|e| e.info.clone()).collect();
+        list.sort_by_key(|info| info.id);
+        list
+    }
+
+    pub fn get(&self, id: u64) -> Option<(ConnectionInfo, Outbox)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .open
+            .get(&id)
+            .map(|e| (e.info.clone(), e.outbox.clone()))
+    }
+
+    /// Open connections from `ip`.
+    pub fn connected_from(&self, ip: IpAddr) -> Vec<u64> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .open
+            .values()
+            .filter(|e| e.info.addr.ip() == ip)
+            .map(|e| e.info.id)
+            .collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.inner.lock().unwrap().open.len()
+    }
+
+    /// Queues `msg` on every open connection, wherever it is.
+    pub fn send_all(&self, msg: &ServerMsg) {
+        let json = serde_json::to_string(msg).unwrap();
+        for entry in self.inner.lock().unwrap().open.values() {
+            entry.outbox.send_json(json.clone(), false);
+        }
+    }
+
+    /// Closes every socket once what is already queued on it has been sent.
+    pub fn close_all(&self) {
+        for entry in self.inner.lock().unwrap().open.values() {
+            entry.outbox.close();
+        }
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        self.inner.lock().unwrap().banned.contains(&ip)
+    }
+
+    /// Bans `ip` until the server restarts. Returns whether it was new.
+    pub fn ban(&self, ip: IpAddr) -> bool {
+        self.inner.lock().unwrap().banned.insert(ip)
+    }
+
+    pub fn unban(&self, ip: IpAddr) -> bool {
+        self.inner.lock().unwrap().banned.remove(&ip)
+    }
+
+    pub fn banned(&self) -> Vec<IpAddr> {
+        let mut list: Vec<IpAddr> = self.inner.lock().unwrap().banned.iter().copied().collect();
+        list.sort();
+        list
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut ConnectionInfo)) {
+        if let Some(entry) = self.inner.lock().unwrap().open.get_mut(&id) {
+            f(&mut entry.info);
+        }
+    }
+}
+
+/// A connection's place in the registry, removed when this is dropped.
+pub struct Registration {
+    id: u64,
+    connections: Connections,
+}
+
+impl Registration {
+    pub fn set_name(&self, name: &str) {
+        self.connections
+            .update(self.id, |info| info.name = Some(name.to_string()));
+    }
+
+    /// Records where the connection is now: `None` for the lobby.
+    pub fn set_seat(&self, seat: Option<(Role, String, Option<u8>)>) {
+        self.connections.update(self.id, |info| match seat {
+            Some((role, room_id, player_id)) => {
+                info.role = role;
+                info.room_id = Some(room_id);
+                info.player_id = player_id;
+            }
+            None => {
+                info.role = Role::Lobby;
+                info.room_id = None;
+                info.player_id = None;
+            }
+        });
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.connections.inner.lock().unwrap().open.remove(&self.id);
+    }
+}
@@ -0,0 +1,25 @@
+//! The web client as compiled into the binary by `build.rs`, so the server
+//! runs from any directory with nothing shipped beside it.
+
+/// One file under `client/www` at build time.
+pub struct Asset {
+    /// Relative to `client/www`, with `/` separators.
+    pub path: &'static str,
+    pub body: &'static [u8],
+    /// Pre-compressed copies, when the `compress-assets` feature made them
+    /// and they came out smaller.
+    pub gzip: Option<&'static [u8]>,
+    pub br: Option<&'static [u8]>,
+    /// Hash of `body`.
+    pub etag: &'static str,
+}
+
+// Sorted by path.
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+pub fn get(path: &str) -> Option<&'static Asset> {
+    ASSETS
+        .binary_search_by(|asset| asset.path.cmp(path))
+        .ok()
+        .map(|i| &ASSETS[i])
+}
@@ -0,0 +1,134 @@
+//! Just enough HTTP/1.1 for the web client, the JSON endpoints and the
+//! WebSocket upgrades that share the game's port.
+
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::time::{self, Duration, Instant};
+
+// Request heads larger than this are refused.
+const MAX_HEAD: usize = 8192;
+/// How long a connection may sit idle, before its first request or between
+/// kept-alive ones, before it is dropped.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A parsed request line and headers.
+pub struct Request {
+    pub method: String,
+    /// Path and query string, as sent.
+    pub target: String,
+    pub version: String,
+    headers: Vec<(String, String)>,
+    // Bytes in the head, blank line included.
+    len: usize,
+}
+
+impl Request {
+    pub fn parse(head: &[u8]) -> Option<Request> {
+        let text = std::str::from_utf8(head).ok()?;
+        let mut lines = text.split("\r\n");
+        let mut request_line = lines.next()?.split(' ');
+        let method = request_line.next()?.to_string();
+        let target = request_line.next()?.to_string();
+        let version = request_line.next()?.to_string();
+        if !version.starts_with("HTTP/1.") || !target.starts_with('/') {
+            return None;
+        }
+        let headers = lines
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        Some(Request {
+            method,
+            target,
+            version,
+            headers,
+            len: head.len(),
+        })
+    }
+
+    /// The first value of header `name`, which must be lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The target without its query string.
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or("/")
+    }
+
+    pub fn is_websocket(&self) -> bool {
+        self.header("upgrade")
+            .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+    }
+
+    /// Whether the client will send another request on this connection.
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.header("connection").unwrap_or("");
+        let has = |token: &str| {
+            connection
+                .split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        };
+        if self.version == "HTTP/1.0" {
+            has("keep-alive")
+        } else {
+            !has("close")
+        }
+    }
+
+    /// Takes the head off the socket. Only peeked until now, so the next
+    /// request on the connection would otherwise see it again.
+    pub async fn consume(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+        let mut head = vec![0; self.len];
+        stream.read_exact(&mut head).await.map(|_| ())
+    }
+
+    /// Consumes the head and reads the body `Content-Length` announces.
+    /// `None` if it is over `max` bytes, or the peer closes or takes longer
+    /// than `IDLE_TIMEOUT` to send it.
+    pub async fn read_body(&self, stream: &mut TcpStream, max: usize) -> Option<Vec<u8>> {
+        let length = match self.header("content-length") {
+            Some(value) => value.parse::<usize>().ok()?,
+            None => 0,
+        };
+        if length > max {
+            return None;
+        }
+        let mut body = vec![0; self.len + length];
+        time::timeout(IDLE_TIMEOUT, stream.read_exact(&mut body))
+            .await
+            .ok()?
+            .ok()?;
+        Some(body.split_off(self.len))
+    }
+}
+
+/// Waits for a complete request head and returns it without consuming it, so
+/// a WebSocket upgrade can still be handed over untouched. `None` if the peer
+/// closes, stays idle for `IDLE_TIMEOUT`, or sends an oversized head.
+pub async fn peek_head(stream: &TcpStream) -> Option<Vec<u8>> {
+    let deadline = Instant::now() + IDLE_TIMEOUT;
+    let mut buf = vec![0u8; MAX_HEAD];
+    loop {
+        let n = time::timeout_at(deadline, stream.peek(&mut buf))
+            .await
+            .ok()?
+            .ok()?;
+        if n == 0 {
+            return None;
+        }
+        if let Some(end) = buf[..n].windows(4).position(|w| w == b"\r\n\r\n") {
+            buf.truncate(end + 4);
+            return Some(buf);
+        }
+        if n == buf.len() {
+            return None;
+        }
+        // The rest of the head is still on its way.
+        time::sleep(Duration::from_millis(10)).await;
+    }
+}
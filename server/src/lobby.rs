@@ -0,0 +1,273 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use rand::Rng;
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::Message;
+
+use shared::protocol::*;
+
+use crate::config::Config;
+use crate::metrics::{self, METRICS};
+use crate::outbox::Outbox;
+use crate::replays::ReplayStore;
+use crate::room::{Room, Seat, WsStream, MAX_SPECTATOR_DELAY};
+use crate::room_task::RoomHandle;
+use crate::Rooms;
+
+const MAX_NAME_LEN: usize = 16;
+const JOIN_CODE_LEN: usize = 5;
+// No 0/O or 1/I so codes survive being read out loud.
+const JOIN_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Settings a client can pick when creating a room.
+#[derive(Default)]
+struct RoomOptions {
+    lockstep: bool,
+    /// Overrides the server's default spectator delay.
+    spectator_delay: Option<Duration>,
+}
+
+/// A socket's seat in a room, from either a fresh join or a resume.
+pub struct Session {
+    pub room: RoomHandle,
+    pub player_id: u8,
+    /// Whether this is an external bot connection.
+    pub agent: bool,
+    /// Spectators watch without a player; `player_id` means nothing for them.
+    pub spectator: bool,
+    pub connection: u64,
+    /// Fires if the host kicks this player; errors if the seat is taken over.
+    pub evicted: oneshot::Receiver<()>,
+}
+
+/// Trims a client-supplied name and caps its length, falling back to
+/// `default` when nothing is left.
+pub fn clean_name(name: &str, default: &str) -> String {
+    let name: String = name.trim().chars().take(MAX_NAME_LEN).collect();
+    if name.is_empty() {
+        default.to_string()
+    } else {
+        name
+    }
+}
+
+/// Serves lobby requests until the player is seated in a room. Returns `None`
+/// if the socket closes first. `agent` marks an external bot connection.
+pub async fn run_lobby(
+    rooms: &Rooms,
+    config: &Config,
+    name: &str,
+    agent: bool,
+    outbox: &Outbox,
+    stream_rx: &mut WsStream,
+) -> Option<Session> {
+    outbox.send(&room_list(rooms).await);
+
+    while let Some(msg_result) = stream_rx.next().await {
+        let text = match msg_result {
+            Ok(Message::Text(text)) => {
+                metrics::inc(&METRICS.messages_in);
+                text
+            }
+            Ok(Message::Close(_)) | Err(_) => return None,
+            _ => continue,
+        };
+        let Ok(client_msg) = serde_json::from_str::<ClientMsg>(&text) else {
+            continue;
+        };
+
+        let target = match client_msg {
+            ClientMsg::ListRooms => {
+                outbox.send(&room_list(rooms).await);
+                continue;
+            }
+            ClientMsg::CreateRoom {
+                name: room_name,
+                private,
+                min_players,
+                max_players,
+                lockstep,
+                spectator_delay_secs,
+            } => {
+                let default = format!("{}'s room", name);
+                let room_name = clean_name(&room_name, &default);
+                let min_players = min_players.unwrap_or(config.rooms.min_players);
+                let max_players = max_players.unwrap_or(config.rooms.max_players);
+                let limits = (min_players, max_players);
+                let options = RoomOptions {
+                    lockstep,
+                    spectator_delay: spectator_delay_secs.map(|s| Duration::from_secs(s.into())),
+                };
+                let room = create_room(rooms, config, room_name, private, limits, options).await;
+                if room.is_none() {
+                    send_error(outbox, "Too many rooms are open");
+                    continue;
+                }
+                room
+            }
+            ClientMsg::JoinRoom { room_id } => {
+                let room = rooms.lock().await.get(&room_id).cloned();
+                room.filter(|room| room.summary().join_code.is_none())
+            }
+            ClientMsg::JoinByCode { code } => find_by_code(rooms, &code).await,
+            ClientMsg::QuickJoin => {
+                let room = quick_join_room(rooms, config).await;
+                if room.is_none() {
+                    send_error(outbox, "Too many rooms are open");
+                    continue;
+                }
+                room
+            }
+            ClientMsg::Spectate { room_id } => {
+                let room = rooms.lock().await.get(&room_id).cloned();
+                let Some(room) = room.filter(|room| room.summary().join_code.is_none()) else {
+                    send_error(outbox, "Room not found");
+                    continue;
+                };
+                match room.spectate(name.to_string(), outbox.clone()).await {
+                    Some(seat) => return Some(session(room, seat)),
+                    None => send_error(outbox, "Room has no spectator slots left"),
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        let Some(room) = target else {
+            send_error(outbox, "Room not found");
+            continue;
+        };
+        match room.join(name.to_string(), agent, outbox.clone()).await {
+            Some(seat) => return Some(session(room, seat)),
+            None => send_error(outbox, "Room is full or already playing"),
+        }
+    }
+
+    None
+}
+
+async fn room_list(rooms: &Rooms) -> ServerMsg {
+    let mut list: Vec<RoomInfo> = rooms
+        .lock()
+        .await
+        .values()
+        .map(RoomHandle::summary)
+        .filter(|summary| summary.join_code.is_none())
+        .map(|summary| summary.info)
+        .collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    ServerMsg::RoomList { rooms: list }
+}
+
+/// Opens a room set up from the server's config and `options`, or returns
+/// `None` if the server already has as many rooms as it allows.
+async fn create_room(
+    rooms: &Rooms,
+    config: &Config,
+    name: String,
+    private: bool,
+    (min_players, max_players): (usize, usize),
+    options: RoomOptions,
+) -> Option<RoomHandle> {
+    let mut rooms_lock = rooms.lock().await;
+    let open = rooms_lock.values().filter(|room| !room.is_closed()).count();
+    if open >= config.rooms.max_rooms {
+        return None;
+    }
+    let join_code = if private {
+        let taken: Vec<String> = rooms_lock
+            .values()
+            .filter_map(|room| room.summary().join_code)
+            .collect();
+        let mut code = generate_join_code();
+        while taken.contains(&code) {
+            code = generate_join_code();
+        }
+        Some(code)
+    } else {
+        None
+    };
+
+    let room_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+    let mut room = Room::new(room_id.clone(), name, join_code, min_players, max_players);
+    room.lockstep = options.lockstep;
+    room.set_rules(config.rules);
+    room.reconnect_grace = config.rooms.reconnect_grace();
+    room.replace_disconnected = config.rooms.replace_disconnected;
+    room.log_inputs = config.log_inputs;
+    room.replays = ReplayStore::new(&config.replays);
+    room.max_spectators = config.rooms.max_spectators;
+    // A room may ask for a longer delay than the server's, never a shorter one.
+    let delay = config.rooms.spectator_delay();
+    room.spectator_delay = options
+        .spectator_delay
+        .map_or(delay, |asked| asked.max(delay).min(MAX_SPECTATOR_DELAY));
+    let room = RoomHandle::spawn(room);
+    rooms_lock.insert(room_id, room.clone());
+    Some(room)
+}
+
+async fn find_by_code(rooms: &Rooms, code: &str) -> Option<RoomHandle> {
+    let code = code.trim().to_uppercase();
+    rooms
+        .lock()
+        .await
+        .values()
+        .find(|room| room.summary().join_code.as_deref() == Some(code.as_str()))
+        .cloned()
+}
+
+async fn quick_join_room(rooms: &Rooms, config: &Config) -> Option<RoomHandle> {
+    let found = rooms
+        .lock()
+        .await
+        .values()
+        .find(|room| {
+            let summary = room.summary();
+            summary.join_code.is_none() && summary.joinable
+        })
+        .cloned();
+
+    match found {
+        Some(room) => Some(room),
+        None => {
+            let name = "Quick match".to_string();
+            let limits = (config.rooms.min_players, config.rooms.max_players);
+            create_room(rooms, config, name, false, limits, RoomOptions::default()).await
+        }
+    }
+}
+
+fn generate_join_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..JOIN_CODE_LEN)
+        .map(|_| JOIN_CODE_CHARS[rng.gen_range(0..JOIN_CODE_CHARS.len())] as char)
+        .collect()
+}
+
+/// Reattaches the connection to the player that `token` belongs to. Tokens
+/// start with the id of the room that issued them.
+pub async fn resume_room(rooms: &Rooms, token: &str, outbox: &Outbox) -> Option<Session> {
+    let (room_id, _) = token.split_once('.')?;
+    let room = rooms.lock().await.get(room_id).cloned()?;
+    let seat = room.resume(token.to_string(), outbox.clone()).await?;
+    Some(session(room, seat))
+}
+
+fn session(room: RoomHandle, seat: Seat) -> Session {
+    Session {
+        room,
+        player_id: seat.player_id,
+        agent: seat.agent,
+        spectator: seat.spectator,
+        connection: seat.connection,
+        evicted: seat.evicted,
+    }
+}
+
+fn send_error(outbox: &Outbox, message: &str) {
+    outbox.send(&ServerMsg::LobbyError {
+        message: message.to_string(),
+    });
+}
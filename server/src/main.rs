@@ -1,244 +1,439 @@
-mod game;
+mod admin;
+mod api;
+mod config;
+mod connections;
+mod embedded;
+mod http;
+mod lobby;
+mod metrics;
+mod outbox;
+mod replays;
 mod room;
+mod room_task;
+mod static_files;
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::path::PathBuf;
 use std::sync::Arc;
 
 use futures_util::StreamExt;
+use log::{error, info, warn};
+use socket2::{Domain, Socket, Type};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Mutex;
 use tokio::time::{self, Duration};
 use tokio_tungstenite::tungstenite::Message;
 
-use shared::constants::*;
 use shared::protocol::*;
 
-use room::Room;
+use admin::Admin;
+use config::Config;
+use connections::{Connections, Role};
+use lobby::Session;
+use metrics::METRICS;
+use outbox::Outbox;
+use replays::ReplayStore;
+use room_task::{RoomCmd, RoomHandle};
 
-type Rooms = Arc<Mutex<HashMap<String, Arc<Mutex<Room>>>>>;
+type Rooms = Arc<Mutex<HashMap<String, RoomHandle>>>;
+
+/// How long a shutdown waits for clients to take their close frames.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(3);
 
 #[tokio::main]
 async fn main() {
-    let addr = "0.0.0.0:3000";
-    let listener = TcpListener::bind(addr).await.expect("Failed to bind");
-    println!("Server running on {}", addr);
-    println!("Open http://localhost:3000 in your browser");
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(2);
+        }
+    };
+    env_logger::Builder::new()
+        .filter_level(config.log_level)
+        .format_target(false)
+        .init();
+
+    let mut listeners = Vec::new();
+    for &addr in &config.listen {
+        match bind(addr) {
+            Ok(listener) => listeners.push(listener),
+            Err(e) => {
+                error!("Failed to listen on {}: {}", addr, e);
+                std::process::exit(1);
+            }
+        }
+        info!("Server running on {}", addr);
+    }
+    info!(
+        "Open http://localhost:{} in your browser",
+        config.listen[0].port()
+    );
+    match &config.static_dir {
+        Some(dir) => info!("Serving the web client from {}", dir.display()),
+        None if embedded::get("index.html").is_none() => {
+            warn!("No web client was built into this server; serve one with --static-dir")
+        }
+        None => {}
+    }
+    match ReplayStore::new(&config.replays) {
+        Some(_) => info!("Saving replays to {}", config.replays.dir.display()),
+        None => info!("Replay recording is off; set replays.dir to turn it on"),
+    }
 
     let rooms: Rooms = Arc::new(Mutex::new(HashMap::new()));
+    tokio::spawn(reap_rooms(rooms.clone()));
+    let connections = Connections::default();
+    let admin = Admin::new(rooms, connections.clone(), config.admin_token.clone());
+    tokio::spawn(admin.clone().run_console());
+
+    // Serve static files and WebSocket on the same port
+    for listener in listeners {
+        tokio::spawn(accept_loop(listener, admin.clone(), config.clone()));
+    }
+    tokio::select! {
+        _ = admin.shutdown_requested() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
 
-    // Serve static files and WebSocket on same port
-    loop {
-        let (stream, addr) = listener.accept().await.expect("Failed to accept");
-        let rooms = rooms.clone();
-        tokio::spawn(handle_connection(stream, addr, rooms));
+    shutdown(&connections).await;
+    // Exit outright: the console's blocking stdin read would otherwise hold
+    // the runtime open.
+    std::process::exit(0);
+}
+
+/// Listens on `addr`. IPv6 addresses accept only IPv6, so `0.0.0.0` and
+/// `[::]` on the same port can both be configured.
+fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
     }
+    // Restarting shouldn't have to wait for old sockets in TIME_WAIT.
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
 }
 
-async fn handle_connection(stream: TcpStream, addr: SocketAddr, rooms: Rooms) {
-    let mut buf = [0u8; 4096];
-    let n = stream.peek(&mut buf).await.unwrap_or(0);
-    let request = String::from_utf8_lossy(&buf[..n]);
+async fn accept_loop(listener: TcpListener, admin: Admin, config: Arc<Config>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                let admin = admin.clone();
+                tokio::spawn(handle_connection(stream, addr, admin, config.clone()));
+            }
+            Err(e) => {
+                // Usually out of file descriptors; give some a chance to close.
+                warn!("Failed to accept: {}", e);
+                time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
 
-    if request.contains("Upgrade: websocket") || request.contains("upgrade: websocket") {
-        handle_websocket(stream, addr, rooms).await;
-    } else {
-        handle_http(stream, &request).await;
+/// Tells every client the server is going away, closes their sockets once
+/// that has been sent, and gives them a moment to acknowledge.
+async fn shutdown(connections: &Connections) {
+    info!("Shutting down");
+    connections.send_all(&ServerMsg::Notice {
+        message: "The server is shutting down".to_string(),
+    });
+    connections.close_all();
+    let deadline = time::Instant::now() + SHUTDOWN_GRACE;
+    while connections.count() > 0 && time::Instant::now() < deadline {
+        time::sleep(Duration::from_millis(100)).await;
     }
 }
 
-async fn handle_http(mut stream: TcpStream, request: &str) {
-    use tokio::io::AsyncWriteExt;
+async fn handle_connection(
+    mut stream: TcpStream,
+    addr: SocketAddr,
+    admin: Admin,
+    config: Arc<Config>,
+) {
+    if admin.connections.is_banned(addr.ip()) {
+        return;
+    }
+    // Plain HTTP requests can follow one another on the connection; an
+    // upgrade or an admin or API request ends it.
+    loop {
+        let Some(head) = http::peek_head(&stream).await else {
+            return;
+        };
+        let Some(request) = http::Request::parse(&head) else {
+            write_response(&mut stream, "400 Bad Request", "text/plain", "Bad Request").await;
+            return;
+        };
 
-    let path = request
-        .lines()
-        .next()
-        .and_then(|line| line.split_whitespace().nth(1))
-        .unwrap_or("/");
+        let path = request.path();
+        if request.is_websocket() {
+            handle_websocket(stream, addr, admin.rooms, admin.connections, &config).await;
+            return;
+        } else if path == "/metrics" {
+            if request.consume(&mut stream).await.is_err() {
+                return;
+            }
+            let body = metrics::render(&admin.rooms, &admin.connections).await;
+            write_response(&mut stream, "200 OK", "text/plain; version=0.0.4", &body).await;
+            return;
+        } else if path.starts_with("/admin/") {
+            let (status, body) = admin.handle_http(&mut stream, &request).await;
+            write_admin(&mut stream, status, &body).await;
+            return;
+        } else if !handle_http(&mut stream, &request, &admin.rooms, &config).await {
+            return;
+        }
+    }
+}
 
-    let www_dir = PathBuf::from("client/www");
+/// Serves the JSON endpoints, saved replays and the web client. Returns
+/// whether the connection can be kept open for another request.
+async fn handle_http(
+    stream: &mut TcpStream,
+    request: &http::Request,
+    rooms: &Rooms,
+    config: &Config,
+) -> bool {
+    if request.consume(stream).await.is_err() {
+        return false;
+    }
+    // The page reads its own query string, e.g. `/?replay=<id>`.
+    let path = request.path();
 
-    let (status, content_type, body) = match path {
-        "/" | "/index.html" => {
-            let content = tokio::fs::read_to_string(www_dir.join("index.html"))
-                .await
-                .unwrap_or_else(|_| "index.html not found".into());
-            ("200 OK", "text/html; charset=utf-8", content)
-        }
-        "/style.css" => {
-            let content = tokio::fs::read_to_string(www_dir.join("style.css"))
-                .await
-                .unwrap_or_else(|_| "".into());
-            ("200 OK", "text/css; charset=utf-8", content)
-        }
-        "/pkg/client_bg.wasm" => {
-            match tokio::fs::read(www_dir.join("pkg/client_bg.wasm")).await {
-                Ok(bytes) => {
-                    let response = format!(
-                        "HTTP/1.1 200 OK\r\nContent-Type: application/wasm\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
-                        bytes.len()
-                    );
-                    let _ = stream.write_all(response.as_bytes()).await;
-                    let _ = stream.write_all(&bytes).await;
-                    return;
-                }
-                Err(_) => ("404 Not Found", "text/plain", "WASM not found".into()),
+    if path.starts_with("/api/") {
+        let (status, body) = api::handle(path, rooms).await;
+        write_json(stream, status, &body).await;
+        return false;
+    }
+
+    let replays = ReplayStore::new(&config.replays);
+    if path == "/replays" || path == "/replays/" {
+        let list = match &replays {
+            Some(store) => store.list().await,
+            None => Vec::new(),
+        };
+        write_json(stream, "200 OK", &serde_json::to_string(&list).unwrap()).await;
+        return false;
+    }
+    if let Some(id) = path.strip_prefix("/replays/") {
+        let replay = match &replays {
+            Some(store) => store.load(id).await,
+            None => None,
+        };
+        match replay {
+            Some(bytes) => {
+                write_json(stream, "200 OK", &String::from_utf8_lossy(&bytes)).await;
             }
+            None => write_response(stream, "404 Not Found", "text/plain", "Replay not found").await,
         }
-        "/pkg/client.js" => {
-            let content = tokio::fs::read_to_string(www_dir.join("pkg/client.js"))
-                .await
-                .unwrap_or_else(|_| "JS not found".into());
-            ("200 OK", "application/javascript; charset=utf-8", content)
-        }
-        _ => ("404 Not Found", "text/plain", "Not Found".into()),
-    };
+        return false;
+    }
+
+    static_files::serve(stream, request, config.static_dir.as_deref()).await
+}
+
+/// Answers with a JSON body, readable from overlays and dashboards served
+/// elsewhere.
+async fn write_json(stream: &mut TcpStream, status: &str, body: &str) {
+    write_response(stream, status, "application/json", body).await;
+}
+
+/// Answers with a body that must never be cached, such as live counters.
+async fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) {
+    let cors = "Access-Control-Allow-Origin: *\r\n";
+    write_uncached(stream, status, content_type, cors, body).await;
+}
+
+/// Answers an admin request, which pages from other sites must not read.
+async fn write_admin(stream: &mut TcpStream, status: &str, body: &str) {
+    write_uncached(stream, status, "application/json", "", body).await;
+}
+
+async fn write_uncached(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    headers: &str,
+    body: &str,
+) {
+    use tokio::io::AsyncWriteExt;
 
     let response = format!(
-        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
         status,
         content_type,
         body.len(),
+        headers,
         body
     );
     let _ = stream.write_all(response.as_bytes()).await;
 }
 
-async fn handle_websocket(stream: TcpStream, addr: SocketAddr, rooms: Rooms) {
+async fn handle_websocket(
+    stream: TcpStream,
+    addr: SocketAddr,
+    rooms: Rooms,
+    connections: Connections,
+    config: &Config,
+) {
     let ws_stream = match tokio_tungstenite::accept_async(stream).await {
         Ok(ws) => ws,
         Err(e) => {
-            eprintln!("WebSocket handshake failed for {}: {}", addr, e);
+            warn!("WebSocket handshake failed for {}: {}", addr, e);
             return;
         }
     };
 
-    println!("New WebSocket connection: {}", addr);
+    info!("New WebSocket connection: {}", addr);
 
     let (sink, mut stream_rx) = ws_stream.split();
-    let sink = sink;
+    let outbox = Outbox::spawn(sink);
+    let registration = connections.register(addr, outbox.clone());
+    let mut name = String::from("Player");
 
-    // Wait for Join message
-    let (name, sink) = loop {
-        match stream_rx.next().await {
+    // Wait for Join or Resume message
+    let mut session = loop {
+        let text = match stream_rx.next().await {
             Some(Ok(Message::Text(text))) => {
-                if let Ok(ClientMsg::Join { name }) = serde_json::from_str(&text) {
-                    break (name, sink);
-                }
+                metrics::inc(&METRICS.messages_in);
+                text
             }
             Some(Ok(_)) => continue,
             _ => return,
-        }
-    };
-
-    // Find or create a room
-    let room_arc = {
-        let mut rooms_lock = rooms.lock().await;
-        let room = rooms_lock
-            .values()
-            .find(|r| {
-                let r = r.try_lock();
-                r.map_or(false, |r| !r.game.running && r.player_count() < MAX_PLAYERS)
-            })
-            .cloned();
-
-        match room {
-            Some(r) => r,
-            None => {
-                let room_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
-                let new_room = Arc::new(Mutex::new(Room::new(room_id.clone())));
-                rooms_lock.insert(room_id, new_room.clone());
-                new_room
+        };
+        let (requested, as_bot) = match serde_json::from_str::<ClientMsg>(&text) {
+            Ok(ClientMsg::Join { name }) => (name, false),
+            Ok(ClientMsg::JoinAsBot { name }) => (name, true),
+            Ok(ClientMsg::Resume { token }) => {
+                match lobby::resume_room(&rooms, &token, &outbox).await {
+                    Some(session) => break session,
+                    None => {
+                        outbox.send(&ServerMsg::ResumeRejected);
+                        info!("Rejected resume from {}", addr);
+                        continue;
+                    }
+                }
             }
+            _ => continue,
+        };
+        name = lobby::clean_name(&requested, "Player");
+        registration.set_name(&name);
+        match lobby::run_lobby(&rooms, config, &name, as_bot, &outbox, &mut stream_rx).await {
+            Some(session) => break session,
+            None => return,
         }
     };
 
-    let player_id;
-    let room_id;
-
-    // Add player to room
-    {
-        let mut room = room_arc.lock().await;
-        player_id = room.add_player(name, sink);
-        room_id = room.id.clone();
-
-        room.send_to(
+    loop {
+        let Session {
+            room,
             player_id,
-            &ServerMsg::Welcome {
-                player_id,
-                room_id: room_id.clone(),
-            },
-        )
-        .await;
-
-        let count = room.player_count();
-        room.broadcast(&ServerMsg::Waiting {
-            player_count: count,
-            need: room.min_players,
-        })
-        .await;
-
-        if room.is_ready() && !room.game.running {
-            room.game.start();
-            let map: Vec<Vec<_>> = room.game.map.iter().map(|row| row.to_vec()).collect();
-            let players = room.game.player_states();
-            room.broadcast(&ServerMsg::GameStart { map, players }).await;
-
-            let room_for_tick = room_arc.clone();
-            tokio::spawn(game_loop(room_for_tick));
-        }
-    }
-
-    println!("Player {} joined room {}", player_id, room_id);
-
-    // Read messages from this player
-    while let Some(msg_result) = stream_rx.next().await {
-        match msg_result {
-            Ok(Message::Text(text)) => {
-                if let Ok(client_msg) = serde_json::from_str::<ClientMsg>(&text) {
-                    let mut room = room_arc.lock().await;
-                    match client_msg {
-                        ClientMsg::Move { dx, dy } => {
-                            room.game.set_player_movement(player_id, dx, dy);
+            agent,
+            spectator,
+            connection,
+            mut evicted,
+        } = session;
+        let role = match (spectator, agent) {
+            (true, _) => Role::Spectator,
+            (false, true) => Role::Agent,
+            (false, false) => Role::Player,
+        };
+        let seated = (!spectator).then_some(player_id);
+        registration.set_seat(Some((role, room.summary().info.id, seated)));
+
+        // Forward messages from this player to the room until they leave,
+        // disconnect or get kicked
+        let exit = loop {
+            let msg_result = tokio::select! {
+                msg = stream_rx.next() => msg,
+                kicked = &mut evicted => break match kicked {
+                    Ok(()) => SeatExit::Kicked,
+                    Err(_) => SeatExit::Replaced,
+                },
+            };
+            match msg_result {
+                Some(Ok(Message::Text(text))) => {
+                    metrics::inc(&METRICS.messages_in);
+                    if let Ok(msg) = serde_json::from_str::<ClientMsg>(&text) {
+                        if let ClientMsg::LeaveRoom = msg {
+                            break SeatExit::Left;
                         }
-                        ClientMsg::PlaceBomb => {
-                            room.game.place_bomb(player_id);
+                        // Spectators only watch.
+                        if spectator {
+                            continue;
                         }
-                        ClientMsg::Join { .. } => {}
+                        room.send(RoomCmd::Msg {
+                            player_id,
+                            connection,
+                            msg,
+                        })
+                        .await;
                     }
                 }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break SeatExit::Closed,
+                _ => {}
             }
-            Ok(Message::Close(_)) | Err(_) => break,
-            _ => {}
-        }
-    }
-
-    println!("Player {} disconnected from room {}", player_id, room_id);
+        };
 
-    {
-        let mut room = room_arc.lock().await;
-        room.remove_player(player_id);
+        match exit {
+            SeatExit::Closed | SeatExit::Left if spectator => {
+                room.send(RoomCmd::Unspectate { connection }).await;
+                if let SeatExit::Closed = exit {
+                    return;
+                }
+            }
+            SeatExit::Closed => {
+                room.send(RoomCmd::Disconnect {
+                    player_id,
+                    connection,
+                })
+                .await;
+                return;
+            }
+            // A resume on another socket took the seat over, or the room went
+            // away, so this connection is done.
+            SeatExit::Replaced => return,
+            SeatExit::Kicked => {}
+            SeatExit::Left => {
+                room.send(RoomCmd::Leave {
+                    player_id,
+                    connection,
+                })
+                .await;
+            }
+        }
+        registration.set_seat(None);
+        let next = lobby::run_lobby(&rooms, config, &name, agent, &outbox, &mut stream_rx).await;
+        session = match next {
+            Some(session) => session,
+            None => return,
+        };
     }
 }
 
-async fn game_loop(room: Arc<Mutex<Room>>) {
-    let mut interval = time::interval(Duration::from_millis(TICK_RATE_MS));
+/// How a connection stopped occupying its seat in a room.
+enum SeatExit {
+    Left,
+    Closed,
+    Kicked,
+    Replaced,
+}
 
+/// Periodically drops rooms whose task has closed them.
+async fn reap_rooms(rooms: Rooms) {
+    let mut interval = time::interval(Duration::from_secs(10));
     loop {
         interval.tick().await;
 
-        let mut r = room.lock().await;
-        if r.game.finished {
-            r.broadcast(&ServerMsg::GameOver {
-                winner: r.game.winner,
-            })
-            .await;
-            break;
-        }
-
-        r.game.tick();
-        let state = r.game.get_state_msg();
-        r.broadcast(&state).await;
+        rooms.lock().await.retain(|id, room| {
+            let closed = room.is_closed();
+            if closed {
+                info!("Removed room {}", id);
+            }
+            !closed
+        });
     }
 }
@@ -0,0 +1,200 @@
+//! Process-wide counters served at `/metrics` in the Prometheus text format.
+//! They are plain atomics so the room tasks and writer tasks can bump them
+//! without locking; gauges such as open rooms are read when scraped.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use shared::protocol::RoomStatus;
+
+use crate::api;
+use crate::connections::Connections;
+use crate::Rooms;
+
+pub static METRICS: Metrics = Metrics::new();
+
+// Upper bounds in seconds, spanning a fast tick up to several missed ones.
+const BUCKETS: [f64; 10] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+];
+
+pub struct Histogram {
+    counts: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Histogram {
+            counts: [const { AtomicU64::new(0) }; BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        // Buckets are cumulative only when rendered.
+        if let Some(i) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.counts[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(&self.counts) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+pub struct Metrics {
+    pub games_started: AtomicU64,
+    pub games_finished: AtomicU64,
+    /// WebSocket text messages received from clients.
+    pub messages_in: AtomicU64,
+    /// WebSocket messages written to clients, close frames included.
+    pub messages_out: AtomicU64,
+    pub bytes_out: AtomicU64,
+    /// Time for a room to serialize a message and queue it for everyone.
+    pub broadcast: Histogram,
+    /// Time a room spends in one `Room::update`.
+    pub tick: Histogram,
+    /// Ticks that finished more than a tick interval after they were due.
+    pub tick_overruns: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            games_started: AtomicU64::new(0),
+            games_finished: AtomicU64::new(0),
+            messages_in: AtomicU64::new(0),
+            messages_out: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            broadcast: Histogram::new(),
+            tick: Histogram::new(),
+            tick_overruns: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Bumps a counter by one.
+pub fn inc(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The exposition served at `/metrics`.
+pub async fn render(rooms: &Rooms, connections: &Connections) -> String {
+    let m = &METRICS;
+    let mut out = String::new();
+
+    gauge(
+        &mut out,
+        "server_connections",
+        "Open WebSocket connections.",
+        connections.count() as u64,
+    );
+
+    let rooms = api::open_rooms(rooms).await;
+    let _ = writeln!(out, "# HELP server_rooms Open rooms by state.");
+    let _ = writeln!(out, "# TYPE server_rooms gauge");
+    for (status, label) in [
+        (RoomStatus::Lobby, "lobby"),
+        (RoomStatus::Countdown, "countdown"),
+        (RoomStatus::Playing, "playing"),
+        (RoomStatus::Intermission, "intermission"),
+    ] {
+        let count = rooms
+            .iter()
+            .filter(|(_, s)| s.info.status == status)
+            .count();
+        let _ = writeln!(out, "server_rooms{{state=\"{label}\"}} {count}");
+    }
+
+    counter(
+        &mut out,
+        "server_games_started_total",
+        "Games started.",
+        &m.games_started,
+    );
+    counter(
+        &mut out,
+        "server_games_finished_total",
+        "Games played to the end.",
+        &m.games_finished,
+    );
+    counter(
+        &mut out,
+        "server_messages_in_total",
+        "WebSocket messages received.",
+        &m.messages_in,
+    );
+    counter(
+        &mut out,
+        "server_messages_out_total",
+        "WebSocket messages sent.",
+        &m.messages_out,
+    );
+    counter(
+        &mut out,
+        "server_bytes_out_total",
+        "WebSocket payload bytes sent.",
+        &m.bytes_out,
+    );
+    m.broadcast.render(
+        &mut out,
+        "server_broadcast_seconds",
+        "Time to serialize and queue a room broadcast.",
+    );
+    m.tick.render(
+        &mut out,
+        "server_tick_duration_seconds",
+        "Time spent advancing a room by one tick.",
+    );
+    counter(
+        &mut out,
+        "server_tick_overruns_total",
+        "Ticks that finished more than a tick interval late, all rooms.",
+        &m.tick_overruns,
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP server_room_tick_overruns Late ticks in each open room."
+    );
+    let _ = writeln!(out, "# TYPE server_room_tick_overruns gauge");
+    for (_, summary) in &rooms {
+        let _ = writeln!(
+            out,
+            "server_room_tick_overruns{{room=\"{}\"}} {}",
+            summary.info.id, summary.tick_overruns
+        );
+    }
+    out
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} gauge");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+}
@@ -0,0 +1,122 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use futures_util::SinkExt;
+use tokio::sync::{mpsc, watch, Notify};
+use tokio_tungstenite::tungstenite::Message;
+
+use shared::protocol::ServerMsg;
+
+use crate::metrics::{self, METRICS};
+use crate::room::WsSink;
+
+// Messages that must arrive (welcome, game start, game over, ...). A client
+// that lets this many pile up is too far behind to catch up and is dropped.
+const RELIABLE_QUEUE: usize = 64;
+
+/// Sending half of a client's writer task. Game state snapshots go through a
+/// single slot that newer snapshots overwrite, so a slow client skips stale
+/// frames instead of stalling the room; everything else is queued in order.
+#[derive(Clone)]
+pub struct Outbox {
+    reliable: mpsc::Sender<Message>,
+    snapshot: Arc<watch::Sender<Option<String>>>,
+    overflow: Arc<Notify>,
+}
+
+impl Outbox {
+    /// Spawns the writer task that owns `sink`. It exits once every `Outbox`
+    /// clone is dropped or the socket fails.
+    pub fn spawn(sink: WsSink) -> Self {
+        let (reliable, reliable_rx) = mpsc::channel(RELIABLE_QUEUE);
+        let (snapshot, snapshot_rx) = watch::channel(None);
+        let overflow = Arc::new(Notify::new());
+        tokio::spawn(write_loop(sink, reliable_rx, snapshot_rx, overflow.clone()));
+        Outbox {
+            reliable,
+            snapshot: Arc::new(snapshot),
+            overflow,
+        }
+    }
+
+    /// An outbox with no socket behind it, whose messages go nowhere.
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        let (reliable, _) = mpsc::channel(RELIABLE_QUEUE);
+        let (snapshot, _) = watch::channel(None);
+        Outbox {
+            reliable,
+            snapshot: Arc::new(snapshot),
+            overflow: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn send(&self, msg: &ServerMsg) {
+        let json = serde_json::to_string(msg).unwrap();
+        self.send_json(json, is_snapshot(msg));
+    }
+
+    /// Queues an already serialized message, so a broadcast only serializes
+    /// once. `snapshot` marks messages that may be replaced by newer ones.
+    pub fn send_json(&self, json: String, snapshot: bool) {
+        if snapshot {
+            self.snapshot.send_replace(Some(json));
+        } else if let Err(mpsc::error::TrySendError::Full(_)) =
+            self.reliable.try_send(Message::Text(json))
+        {
+            self.overflow.notify_one();
+        }
+    }
+
+    /// Closes the socket once everything queued before it has been sent.
+    pub fn close(&self) {
+        if self.reliable.try_send(Message::Close(None)).is_err() {
+            self.overflow.notify_one();
+        }
+    }
+}
+
+/// Per-tick messages that a newer one makes obsolete.
+pub fn is_snapshot(msg: &ServerMsg) -> bool {
+    matches!(msg, ServerMsg::GameState { .. } | ServerMsg::Observation(_))
+}
+
+async fn write_loop(
+    mut sink: WsSink,
+    mut reliable: mpsc::Receiver<Message>,
+    mut snapshot: watch::Receiver<Option<String>>,
+    overflow: Arc<Notify>,
+) {
+    loop {
+        let msg = tokio::select! {
+            biased;
+            _ = overflow.notified() => {
+                let _ = sink.send(Message::Close(None)).await;
+                break;
+            }
+            msg = reliable.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            changed = snapshot.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                match snapshot.borrow_and_update().clone() {
+                    Some(text) => Message::Text(text),
+                    None => continue,
+                }
+            }
+        };
+        let close = matches!(msg, Message::Close(_));
+        let len = msg.len() as u64;
+        if sink.send(msg).await.is_err() {
+            break;
+        }
+        metrics::inc(&METRICS.messages_out);
+        METRICS.bytes_out.fetch_add(len, Ordering::Relaxed);
+        if close {
+            break;
+        }
+    }
+}
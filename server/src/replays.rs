@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+use log::{info, warn};
+use serde::Serialize;
+use sim::replay::Replay;
+
+use crate::config::ReplayConfig;
+
+/// Where finished games are saved, and how many to keep.
+#[derive(Clone, Debug)]
+pub struct ReplayStore {
+    dir: PathBuf,
+    max_files: usize,
+    max_bytes: u64,
+}
+
+/// A saved replay as listed at `/replays`.
+#[derive(Serialize)]
+pub struct ReplayEntry {
+    pub id: String,
+    pub bytes: u64,
+}
+
+impl ReplayStore {
+    /// `None` when recording is turned off by an empty directory.
+    pub fn new(config: &ReplayConfig) -> Option<Self> {
+        if config.dir.as_os_str().is_empty() {
+            return None;
+        }
+        Some(ReplayStore {
+            dir: config.dir.clone(),
+            max_files: config.max_files,
+            max_bytes: config.max_mb * 1024 * 1024,
+        })
+    }
+
+    /// Writes a replay in the background, then deletes the oldest ones until
+    /// the directory is within its limits.
+    pub fn save(&self, replay: Replay) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = store.write(&replay).await {
+                warn!("Failed to save replay {}: {}", replay.id, e);
+                return;
+            }
+            store.rotate().await;
+        });
+    }
+
+    async fn write(&self, replay: &Replay) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let json = serde_json::to_vec(replay).unwrap();
+        // Write then rename so a listed replay is always complete.
+        let tmp = self.dir.join(format!("{}.json.tmp", replay.id));
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, self.dir.join(format!("{}.json", replay.id))).await
+    }
+
+    async fn rotate(&self) {
+        let mut entries = self.list().await;
+        let mut total: u64 = entries.iter().map(|e| e.bytes).sum();
+        // Oldest first, since ids start with the time the game started.
+        entries.reverse();
+        let mut count = entries.len();
+        for entry in entries {
+            if count <= self.max_files && total <= self.max_bytes {
+                break;
+            }
+            if tokio::fs::remove_file(self.path(&entry.id)).await.is_ok() {
+                info!("Removed old replay {}", entry.id);
+            }
+            count -= 1;
+            total -= entry.bytes;
+        }
+    }
+
+    /// Saved replays, newest first.
+    pub async fn list(&self) -> Vec<ReplayEntry> {
+        let mut entries = Vec::new();
+        let Ok(mut dir) = tokio::fs::read_dir(&self.dir).await else {
+            return entries;
+        };
+        while let Ok(Some(file)) = dir.next_entry().await {
+            let name = file.file_name().to_string_lossy().into_owned();
+            let Some(id) = name.strip_suffix(".json") else {
+                continue;
+            };
+            if !is_valid_id(id) {
+                continue;
+            }
+            let bytes = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+            entries.push(ReplayEntry {
+                id: id.to_string(),
+                bytes,
+            });
+        }
+        entries.sort_by(|a, b| b.id.cmp(&a.id));
+        entries
+    }
+
+    /// The replay file's JSON, if `id` names one.
+    pub async fn load(&self, id: &str) -> Option<Vec<u8>> {
+        if !is_valid_id(id) {
+            return None;
+        }
+        tokio::fs::read(self.path(id)).await.ok()
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
+
+/// Ids are `<unix millis>-<room id>`; anything else could escape the
+/// directory.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// A new replay id for a game in `room_id` starting now, which sorts by
+/// start time.
+pub fn new_id(room_id: &str, started_at_ms: u128) -> String {
+    format!("{:013}-{}", started_at_ms, room_id)
+}
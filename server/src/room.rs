@@ -1,72 +1,956 @@
-use std::collections::HashMap;
-use futures_util::stream::SplitSink;
-use futures_util::SinkExt;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures_util::stream::{SplitSink, SplitStream};
+use log::info;
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use tokio::sync::oneshot;
 use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::WebSocketStream;
 
+use shared::constants::{MAX_PLAYERS, MIN_PLAYERS, START_COUNTDOWN_SECS};
 use shared::protocol::*;
+use shared::rules::Rules;
+use sim::game::GameInstance;
+use sim::replay::{Replay, LIVE_HASH_INTERVAL};
+
+use crate::metrics::{self, METRICS};
+use crate::outbox::{self, Outbox};
+use crate::replays::{self, ReplayStore};
+
+pub type WsSink = SplitSink<WebSocketStream<TcpStream>, Message>;
+pub type WsStream = SplitStream<WebSocketStream<TcpStream>>;
 
-use crate::game::GameInstance;
+/// How long a disconnected player's character waits for a `Resume`.
+pub const DEFAULT_RECONNECT_GRACE: Duration = Duration::from_secs(30);
+/// How long a room with nobody in it is kept before it is removed.
+pub const EMPTY_ROOM_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the results stay up before the room returns to its lobby.
+pub const INTERMISSION: Duration = Duration::from_secs(8);
+/// How long a lockstep room waits for a slow external bot before ticking
+/// without it.
+pub const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+/// How many spectators a room admits unless configured otherwise.
+pub const DEFAULT_MAX_SPECTATORS: usize = 8;
+/// Longest delay a spectator feed can be set to, which bounds its buffer.
+pub const MAX_SPECTATOR_DELAY: Duration = Duration::from_secs(60);
+/// Longest game recorded, half an hour of ticks. Recording a longer one is
+/// given up so it can't hold ever more memory.
+pub const MAX_REPLAY_TICKS: usize = 36_000;
 
-type WsSink = SplitSink<WebSocketStream<TcpStream>, Message>;
+struct Connection {
+    id: u64,
+    // Fired when the host kicks the player. Dropping it without sending tells
+    // the connection task its seat was taken over or the room closed.
+    evict: oneshot::Sender<()>,
+}
+
+struct Spectator {
+    name: String,
+    outbox: Outbox,
+    // Fired when the room is closed on them, as for `Connection`.
+    evict: oneshot::Sender<()>,
+}
 
+// A broadcast held back from spectators until `due`.
+struct DelayedMsg {
+    due: Instant,
+    json: String,
+    snapshot: bool,
+    // Whether it sets up a new screen (see `Room::spectator_view`).
+    view: bool,
+}
+
+/// What joining, resuming or spectating hands to the connection task.
+pub struct Seat {
+    /// Meaningless for spectators, who have no player.
+    pub player_id: u8,
+    pub agent: bool,
+    pub spectator: bool,
+    pub connection: u64,
+    pub evicted: oneshot::Receiver<()>,
+}
+
+/// Room state. Owned by the room's task (see `room_task`), which feeds it
+/// commands and calls `update` every tick, so nothing here awaits.
 pub struct Room {
     pub id: String,
+    pub name: String,
+    /// Private rooms are hidden from the room list and joined by this code.
+    pub join_code: Option<String>,
     pub game: GameInstance,
-    pub sinks: HashMap<u8, Mutex<WsSink>>,
-    pub next_player_id: u8,
     pub min_players: usize,
+    pub max_players: usize,
+    pub reconnect_grace: Duration,
+    pub host: Option<u8>,
+    /// Rules each new round is played with.
+    pub rules: Rules,
+    /// Difficulty of the bot that takes over a player who disconnects
+    /// mid-game, or `None` to leave their character standing idle.
+    pub replace_disconnected: Option<BotDifficulty>,
+    /// Prints every tick's applied inputs, for debugging desyncs.
+    pub log_inputs: bool,
+    /// Tick only once every external bot has acted (see `ClientMsg::Act`).
+    pub lockstep: bool,
+    /// Where finished games are saved; `None` records nothing.
+    pub replays: Option<ReplayStore>,
+    pub max_spectators: usize,
+    /// How far behind the game spectators see it, so players can't watch
+    /// a spectator screen for an edge. Players always get real-time state.
+    pub spectator_delay: Duration,
+    clients: HashMap<u8, Outbox>,
+    // Keyed by connection id, so they are listed in the order they came in.
+    spectators: BTreeMap<u64, Spectator>,
+    // Broadcasts not yet released to spectators, oldest first, and the last
+    // released one that set up a screen, to catch up new spectators with,
+    // and the last released game state. Only kept while someone watches.
+    spectator_feed: VecDeque<DelayedMsg>,
+    spectator_view: Option<String>,
+    spectator_state: Option<String>,
+    // Bots seated from the lobby. They keep their seat between rounds.
+    bots: HashMap<u8, BotDifficulty>,
+    // External bot connections, which get observations instead of state, and
+    // which of them have acted since the last tick.
+    agents: HashSet<u8>,
+    acted: HashSet<u8>,
+    last_step: Instant,
+    replay: Option<Replay>,
+    status: RoomStatus,
+    empty_since: Option<Instant>,
+    ready: HashSet<u8>,
+    sessions: HashMap<String, u8>,
+    disconnected: HashMap<u8, Instant>,
+    // Which connection currently owns each player, so a stale socket that
+    // closes after its player resumed elsewhere doesn't disconnect them.
+    connections: HashMap<u8, Connection>,
+    next_connection: u64,
+    // Seconds still to announce, and when to announce the next one.
+    countdown: Option<(u32, Instant)>,
+    intermission_until: Option<Instant>,
 }
 
 impl Room {
-    pub fn new(id: String) -> Self {
+    /// Creates a room with player limits, clamped to what the map supports.
+    pub fn new(
+        id: String,
+        name: String,
+        join_code: Option<String>,
+        min_players: usize,
+        max_players: usize,
+    ) -> Self {
+        let max_players = max_players.clamp(MIN_PLAYERS, MAX_PLAYERS);
+        let min_players = min_players.clamp(MIN_PLAYERS, max_players);
         Room {
             id,
-            game: GameInstance::new(),
-            sinks: HashMap::new(),
-            next_player_id: 0,
-            min_players: 2,
+            name,
+            join_code,
+            game: GameInstance::new(Rules::default(), rand::random()),
+            min_players,
+            max_players,
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
+            host: None,
+            rules: Rules::default(),
+            replace_disconnected: None,
+            log_inputs: false,
+            lockstep: false,
+            replays: None,
+            max_spectators: DEFAULT_MAX_SPECTATORS,
+            spectator_delay: Duration::ZERO,
+            clients: HashMap::new(),
+            spectators: BTreeMap::new(),
+            spectator_feed: VecDeque::new(),
+            spectator_view: None,
+            spectator_state: None,
+            bots: HashMap::new(),
+            agents: HashSet::new(),
+            acted: HashSet::new(),
+            last_step: Instant::now(),
+            replay: None,
+            status: RoomStatus::Lobby,
+            empty_since: Some(Instant::now()),
+            ready: HashSet::new(),
+            sessions: HashMap::new(),
+            disconnected: HashMap::new(),
+            connections: HashMap::new(),
+            next_connection: 0,
+            countdown: None,
+            intermission_until: None,
         }
     }
 
-    pub fn add_player(&mut self, name: String, sink: WsSink) -> u8 {
-        let pid = self.next_player_id;
-        self.next_player_id += 1;
+    /// Seats a new player if the room is open and sends them their welcome.
+    /// The first player in becomes the host. `agent` marks an external bot.
+    pub fn join(&mut self, name: String, agent: bool, outbox: Outbox) -> Option<Seat> {
+        if !self.is_joinable() {
+            return None;
+        }
+
+        let pid = self.free_player_id()?;
         self.game.add_player(pid, name);
-        self.sinks.insert(pid, Mutex::new(sink));
-        pid
+        if self.host.is_none() {
+            self.host = Some(pid);
+        }
+        self.empty_since = None;
+        if agent {
+            self.agents.insert(pid);
+        }
+        let seat = self.attach(pid, outbox);
+        // Prefixed with the room id so the lobby can route a resume straight here.
+        let token = format!("{}.{}", self.id, uuid::Uuid::new_v4());
+        self.sessions.insert(token.clone(), pid);
+
+        self.send_to(pid, &self.welcome_msg(pid, token));
+        self.send_to(pid, &self.spectators_msg());
+        self.broadcast(&self.waiting_msg());
+        info!("Player {} joined room {}", pid, self.id);
+        Some(seat)
+    }
+
+    // The lowest id no seat holds, so ids stay small however many players
+    // come and go. Seats are only handed out in the lobby, where everyone in
+    // the game still holds one.
+    fn free_player_id(&self) -> Option<u8> {
+        (0..MAX_PLAYERS as u8).find(|id| self.game.players.iter().all(|p| p.id != *id))
+    }
+
+    fn attach(&mut self, player_id: u8, outbox: Outbox) -> Seat {
+        self.next_connection += 1;
+        let (evict, evicted) = oneshot::channel();
+        self.clients.insert(player_id, outbox);
+        self.connections.insert(
+            player_id,
+            Connection {
+                id: self.next_connection,
+                evict,
+            },
+        );
+        Seat {
+            player_id,
+            agent: self.agents.contains(&player_id),
+            spectator: false,
+            connection: self.next_connection,
+            evicted,
+        }
+    }
+
+    fn owns(&self, player_id: u8, connection: u64) -> bool {
+        self.connections
+            .get(&player_id)
+            .is_some_and(|c| c.id == connection)
+    }
+
+    fn welcome_msg(&self, player_id: u8, resume_token: String) -> ServerMsg {
+        ServerMsg::Welcome {
+            player_id,
+            room_id: self.id.clone(),
+            room_name: self.name.clone(),
+            join_code: self.join_code.clone(),
+            resume_token,
+        }
+    }
+
+    /// Attaches the outbox to the player that owns `token`, replacing any
+    /// socket the server hasn't noticed is dead yet, and catches the client
+    /// up on the room's state.
+    pub fn resume(&mut self, token: &str, outbox: Outbox) -> Option<Seat> {
+        let pid = *self.sessions.get(token)?;
+        let seat = self.attach(pid, outbox);
+        self.disconnected.remove(&pid);
+        self.empty_since = None;
+        if self.game.is_bot(pid) {
+            self.game.set_bot(pid, None);
+        }
+
+        self.send_to(pid, &self.welcome_msg(pid, token.to_string()));
+        self.send_to(pid, &self.spectators_msg());
+        self.send_to(pid, &self.catch_up_msg());
+        self.send_observation(pid);
+        info!("Player {} resumed in room {}", pid, self.id);
+        Some(seat)
+    }
+
+    /// Admits a spectator while there is room for one, in any state but
+    /// closed, and catches them up on the room's state.
+    pub fn spectate(&mut self, name: String, outbox: Outbox) -> Option<Seat> {
+        if self.status == RoomStatus::Closed || self.spectators.len() >= self.max_spectators {
+            return None;
+        }
+        self.next_connection += 1;
+        let connection = self.next_connection;
+        let (evict, evicted) = oneshot::channel();
+        outbox.send(&ServerMsg::Spectating {
+            room_id: self.id.clone(),
+            room_name: self.name.clone(),
+            delay_secs: self.spectator_delay.as_secs() as u32,
+        });
+        // A delayed spectator catches up on the delayed view, which before
+        // anything has been released is the room's lobby.
+        match (&self.spectator_view, self.spectator_delay.is_zero()) {
+            (Some(json), false) => outbox.send_json(json.clone(), false),
+            (None, false) => outbox.send(&self.waiting_msg()),
+            (_, true) => outbox.send(&self.catch_up_msg()),
+        }
+        if self.spectators.is_empty() && !self.spectator_delay.is_zero() {
+            // Nothing was buffered while nobody watched, so the delayed feed
+            // starts from the screen the room is on now.
+            self.spectator_feed.push_back(DelayedMsg {
+                due: Instant::now() + self.spectator_delay,
+                json: serde_json::to_string(&self.catch_up_msg()).unwrap(),
+                snapshot: false,
+                view: true,
+            });
+        }
+        outbox.send(&self.spectators_msg());
+        self.spectators.insert(
+            connection,
+            Spectator {
+                name,
+                outbox,
+                evict,
+            },
+        );
+        self.broadcast(&self.spectators_msg());
+        info!("Spectator {} joined room {}", connection, self.id);
+        Some(Seat {
+            player_id: 0,
+            agent: false,
+            spectator: true,
+            connection,
+            evicted,
+        })
+    }
+
+    /// Sends spectators every broadcast that has waited out the delay.
+    fn release_spectator_feed(&mut self) {
+        let now = Instant::now();
+        while let Some(msg) = self.spectator_feed.front() {
+            if msg.due > now {
+                break;
+            }
+            let msg = self.spectator_feed.pop_front().unwrap();
+            for spectator in self.spectators.values() {
+                spectator.outbox.send_json(msg.json.clone(), msg.snapshot);
+            }
+            if msg.view {
+                self.spectator_view = Some(msg.json);
+            } else if msg.snapshot {
+                self.spectator_state = Some(msg.json);
+            }
+        }
+    }
+
+    /// The current game state as `GameState` JSON, as far as spectators have
+    /// seen it, or `None` if the delayed feed hasn't released one yet, which
+    /// it only does while someone is spectating.
+    pub fn state_json(&self) -> Option<String> {
+        if self.spectator_delay.is_zero() {
+            Some(serde_json::to_string(&self.game.get_state_msg()).unwrap())
+        } else {
+            self.spectator_state.clone()
+        }
+    }
+
+    /// Called when a spectator leaves or their socket closes.
+    pub fn remove_spectator(&mut self, connection: u64) {
+        if self.spectators.remove(&connection).is_some() {
+            info!("Spectator {} left room {}", connection, self.id);
+            if self.spectators.is_empty() {
+                self.spectator_feed.clear();
+                self.spectator_view = None;
+                self.spectator_state = None;
+            }
+            self.broadcast(&self.spectators_msg());
+        }
+    }
+
+    fn spectators_msg(&self) -> ServerMsg {
+        ServerMsg::Spectators {
+            names: self.spectators.values().map(|s| s.name.clone()).collect(),
+        }
+    }
+
+    // What a client arriving mid-way needs to pick up the room's state.
+    fn catch_up_msg(&self) -> ServerMsg {
+        match self.status {
+            RoomStatus::Playing => self.game_start_msg(),
+            RoomStatus::Intermission => ServerMsg::GameOver {
+                winner: self.game.winner,
+            },
+            _ => self.waiting_msg(),
+        }
+    }
+
+    /// Applies a message from a seated player. Messages from a connection
+    /// that no longer owns the seat are ignored.
+    pub fn handle_msg(&mut self, player_id: u8, connection: u64, msg: ClientMsg) {
+        if !self.owns(player_id, connection) {
+            return;
+        }
+        match msg {
+            ClientMsg::Move { dx, dy, seq } => {
+                self.game.queue_player_input(player_id, seq, dx, dy);
+            }
+            ClientMsg::PlaceBomb => {
+                self.game.queue_bomb(player_id);
+            }
+            ClientMsg::SetReady { ready } if self.status == RoomStatus::Lobby => {
+                if ready {
+                    self.ready.insert(player_id);
+                } else {
+                    self.ready.remove(&player_id);
+                }
+                self.broadcast(&self.waiting_msg());
+            }
+            ClientMsg::StartGame if self.host == Some(player_id) && self.can_start() => {
+                self.transition(RoomStatus::Countdown);
+                self.countdown = Some((START_COUNTDOWN_SECS, Instant::now()));
+            }
+            ClientMsg::Kick { player_id: target } => {
+                self.kick_player(player_id, target);
+            }
+            ClientMsg::AddBot { difficulty } if self.host == Some(player_id) => {
+                self.add_bot(difficulty);
+            }
+            ClientMsg::Act { tick, action } if self.agents.contains(&player_id) => {
+                self.act(player_id, tick, action);
+            }
+            _ => {}
+        }
+    }
+
+    /// Called when a player's socket closes. During a game the character stays
+    /// idle for `reconnect_grace`; otherwise the player is removed right away.
+    pub fn disconnect_player(&mut self, player_id: u8, connection: u64) {
+        if !self.owns(player_id, connection) {
+            return;
+        }
+        info!("Player {} disconnected from room {}", player_id, self.id);
+        self.connections.remove(&player_id);
+        if self.status == RoomStatus::Playing {
+            self.clients.remove(&player_id);
+            self.game.set_bot(player_id, self.replace_disconnected);
+            self.disconnected.insert(player_id, Instant::now());
+            self.note_if_empty();
+        } else {
+            self.remove_player(player_id);
+            self.broadcast_waiting();
+        }
+    }
+
+    /// Takes a player out of the room so their connection can return to the
+    /// lobby.
+    pub fn leave_player(&mut self, player_id: u8, connection: u64) {
+        if !self.owns(player_id, connection) {
+            return;
+        }
+        info!("Player {} left room {}", player_id, self.id);
+        self.remove_player(player_id);
+        self.broadcast_waiting();
+    }
+
+    /// Host only: removes `target` and tells their connection task to return
+    /// to the lobby.
+    fn kick_player(&mut self, by: u8, target: u8) {
+        if self.host != Some(by) || by == target {
+            return;
+        }
+        self.kick(target);
     }
 
+    /// Removes `target`, a connected player or a lobby bot, sending a
+    /// player's connection back to the lobby.
+    pub fn kick(&mut self, target: u8) {
+        if self.status == RoomStatus::Lobby && self.bots.remove(&target).is_some() {
+            self.game.remove_player(target);
+            self.broadcast_waiting();
+            return;
+        }
+        if !self.clients.contains_key(&target) {
+            return;
+        }
+        self.send_to(target, &ServerMsg::Kicked);
+        let connection = self.connections.remove(&target);
+        self.remove_player(target);
+        if let Some(connection) = connection {
+            let _ = connection.evict.send(());
+        }
+        info!("Player {} kicked from room {}", target, self.id);
+        self.broadcast_waiting();
+    }
+
+    /// Tells everyone in the room `reason`, sends them all back to the lobby
+    /// and closes the room. A game in progress is abandoned unrecorded.
+    pub fn close(&mut self, reason: &str) {
+        let notice = ServerMsg::Notice {
+            message: reason.to_string(),
+        };
+        for outbox in self.clients.values() {
+            outbox.send(&notice);
+        }
+        for (_, connection) in self.connections.drain() {
+            let _ = connection.evict.send(());
+        }
+        for (_, spectator) in std::mem::take(&mut self.spectators) {
+            spectator.outbox.send(&notice);
+            let _ = spectator.evict.send(());
+        }
+        self.clients.clear();
+        self.transition(RoomStatus::Closed);
+        info!("Room {} closed: {}", self.id, reason);
+    }
+
+    /// Sets the rules the next round is played with. A round that hasn't
+    /// started yet picks them up too.
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+        if matches!(self.status, RoomStatus::Lobby | RoomStatus::Countdown) {
+            self.game.rules = rules;
+        }
+    }
+
+    /// Seats a bot in the lobby if there is a free seat.
+    fn add_bot(&mut self, difficulty: BotDifficulty) {
+        if !self.is_joinable() {
+            return;
+        }
+        let Some(pid) = self.free_player_id() else {
+            return;
+        };
+        let name = format!("{:?} Bot {}", difficulty, pid);
+        self.game.add_bot(pid, name, difficulty);
+        self.bots.insert(pid, difficulty);
+        self.broadcast_waiting();
+    }
+
+    /// Queues an external bot's action for the next tick. Only the first
+    /// action per tick counts, and one answering any observation but the
+    /// latest is dropped, so a late answer can't land on a later tick.
+    fn act(&mut self, player_id: u8, tick: u64, action: BotAction) {
+        if self.status != RoomStatus::Playing || tick != self.game.tick {
+            return;
+        }
+        if !self.acted.insert(player_id) {
+            return;
+        }
+        let (dx, dy) = action.direction();
+        let seq = self.game.tick as u32 + 1;
+        self.game.queue_player_input(player_id, seq, dx, dy);
+        if action == BotAction::Bomb {
+            self.game.queue_bomb(player_id);
+        }
+        if self.lockstep && self.all_agents_acted() {
+            self.update_game();
+        }
+    }
+
+    // Whether every connected external bot still in the game has acted.
+    fn all_agents_acted(&self) -> bool {
+        self.agents
+            .iter()
+            .filter(|pid| self.clients.contains_key(pid))
+            .filter(|pid| self.game.players.iter().any(|p| p.id == **pid && p.alive))
+            .all(|pid| self.acted.contains(pid))
+    }
+
+    fn send_observation(&self, player_id: u8) {
+        if self.status != RoomStatus::Playing || !self.agents.contains(&player_id) {
+            return;
+        }
+        if let Some(observation) = self.game.observation(player_id) {
+            self.send_to(player_id, &ServerMsg::Observation(observation));
+        }
+    }
+
+    /// Removes players whose reconnect grace period has run out.
+    fn expire_disconnected(&mut self) {
+        let grace = self.reconnect_grace;
+        let expired: Vec<u8> = self
+            .disconnected
+            .iter()
+            .filter(|(_, since)| since.elapsed() >= grace)
+            .map(|(pid, _)| *pid)
+            .collect();
+        for pid in expired {
+            self.remove_player(pid);
+        }
+    }
+
+    /// Whether the host may start: enough players, and everyone but the host
+    /// has readied up.
+    pub fn can_start(&self) -> bool {
+        self.status == RoomStatus::Lobby
+            && self.seat_count() >= self.min_players
+            && self
+                .clients
+                .keys()
+                .all(|pid| Some(*pid) == self.host || self.ready.contains(pid))
+    }
+
+    pub fn waiting_msg(&self) -> ServerMsg {
+        let players = self
+            .game
+            .players
+            .iter()
+            .map(|p| RoomMember {
+                id: p.id,
+                name: p.name.clone(),
+                ready: self.ready.contains(&p.id) || self.bots.contains_key(&p.id),
+                bot: self.bots.get(&p.id).copied(),
+            })
+            .collect();
+        ServerMsg::Waiting {
+            player_count: self.seat_count(),
+            need: self.min_players,
+            max_players: self.max_players,
+            host: self.host,
+            players,
+        }
+    }
+
+    // The roster only matters to clients while the room is in its lobby.
+    fn broadcast_waiting(&mut self) {
+        if self.status == RoomStatus::Lobby {
+            self.broadcast(&self.waiting_msg());
+        }
+    }
+
+    fn game_start_msg(&self) -> ServerMsg {
+        ServerMsg::GameStart {
+            map: self.game.map.iter().map(|row| row.to_vec()).collect(),
+            players: self.game.player_states(),
+            rules: self.game.rules,
+        }
+    }
+
+    /// Connected humans.
     pub fn player_count(&self) -> usize {
-        self.sinks.len()
+        self.clients.len()
     }
 
-    pub fn is_ready(&self) -> bool {
-        self.player_count() >= self.min_players
+    /// Connected humans plus lobby bots.
+    pub fn seat_count(&self) -> usize {
+        self.clients.len() + self.bots.len()
     }
 
-    pub async fn broadcast(&self, msg: &ServerMsg) {
-        let json = serde_json::to_string(msg).unwrap();
-        for sink in self.sinks.values() {
-            let mut s = sink.lock().await;
-            let _ = s.send(Message::Text(json.clone())).await;
+    pub fn is_joinable(&self) -> bool {
+        self.status == RoomStatus::Lobby && self.seat_count() < self.max_players
+    }
+
+    pub fn status(&self) -> RoomStatus {
+        self.status
+    }
+
+    /// Moves the room to `next`, refusing transitions the lifecycle doesn't
+    /// allow. Returns whether the room changed state.
+    pub fn transition(&mut self, next: RoomStatus) -> bool {
+        if !self.status.can_transition_to(next) {
+            return false;
+        }
+        self.status = next;
+        if next == RoomStatus::Closed {
+            // Nobody can resume into a closed room.
+            self.sessions.clear();
+            self.disconnected.clear();
+            // Spectators go back to the lobby rather than lose their socket.
+            for (_, spectator) in std::mem::take(&mut self.spectators) {
+                let _ = spectator.evict.send(());
+            }
+        }
+        true
+    }
+
+    /// Whether nobody is connected or waiting to resume.
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty() && self.disconnected.is_empty()
+    }
+
+    fn note_if_empty(&mut self) {
+        if self.clients.is_empty() && self.empty_since.is_none() {
+            self.empty_since = Some(Instant::now());
         }
     }
 
-    pub async fn send_to(&self, player_id: u8, msg: &ServerMsg) {
+    /// Advances the room by one tick: runs the countdown, the game and the
+    /// intermission, and closes the room once it has been empty too long.
+    pub fn update(&mut self) {
+        self.release_spectator_feed();
+        match self.status {
+            RoomStatus::Lobby => {
+                let expired = self
+                    .empty_since
+                    .is_some_and(|since| since.elapsed() >= EMPTY_ROOM_TIMEOUT);
+                if self.is_empty() && expired {
+                    self.transition(RoomStatus::Closed);
+                }
+            }
+            RoomStatus::Countdown => self.update_countdown(),
+            RoomStatus::Playing => self.update_game(),
+            RoomStatus::Intermission => {
+                let over = self
+                    .intermission_until
+                    .is_some_and(|until| Instant::now() >= until);
+                if over && self.player_count() == 0 {
+                    self.transition(RoomStatus::Closed);
+                } else if over {
+                    self.reset_for_next_round();
+                    self.broadcast(&self.waiting_msg());
+                }
+            }
+            RoomStatus::Closed => {}
+        }
+    }
+
+    /// Announces one second of the countdown at a time, then starts the game.
+    /// Aborts back to the lobby if the room drops below its minimum.
+    fn update_countdown(&mut self) {
+        let Some((seconds_left, next_at)) = self.countdown else {
+            return;
+        };
+        if Instant::now() < next_at {
+            return;
+        }
+        if self.seat_count() < self.min_players {
+            self.countdown = None;
+            self.transition(RoomStatus::Lobby);
+            self.broadcast(&self.waiting_msg());
+            return;
+        }
+        if seconds_left > 0 {
+            self.broadcast(&ServerMsg::Countdown { seconds_left });
+            self.countdown = Some((seconds_left - 1, next_at + Duration::from_secs(1)));
+            return;
+        }
+
+        self.countdown = None;
+        self.transition(RoomStatus::Playing);
+        self.game.start();
+        if self.replays.is_some() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let id = replays::new_id(&self.id, now.as_millis());
+            let replay = Replay::new(id, now.as_secs(), &self.game);
+            self.replay = Some(replay.with_hash_interval(LIVE_HASH_INTERVAL));
+        }
+        self.broadcast(&self.game_start_msg());
+        metrics::inc(&METRICS.games_started);
+        self.acted.clear();
+        self.last_step = Instant::now();
+        for pid in &self.agents {
+            self.send_observation(*pid);
+        }
+    }
+
+    fn update_game(&mut self) {
+        if self.is_empty() {
+            // Everyone left and nobody can resume; stop simulating.
+            self.transition(RoomStatus::Closed);
+            info!("Room {} closed: no players left", self.id);
+            return;
+        }
+        if self.game.finished {
+            self.broadcast(&ServerMsg::GameOver {
+                winner: self.game.winner,
+            });
+            metrics::inc(&METRICS.games_finished);
+            if let (Some(mut replay), Some(store)) = (self.replay.take(), &self.replays) {
+                replay.finish(&self.game);
+                store.save(replay);
+            }
+            self.transition(RoomStatus::Intermission);
+            self.intermission_until = Some(Instant::now() + INTERMISSION);
+            return;
+        }
+        if self.lockstep && !self.all_agents_acted() && self.last_step.elapsed() < STEP_TIMEOUT {
+            return;
+        }
+
+        self.expire_disconnected();
+        if let Some(inputs) = self.game.tick() {
+            if self.log_inputs {
+                info!(
+                    "room {} inputs {}",
+                    self.id,
+                    serde_json::to_string(&inputs).unwrap()
+                );
+            }
+            if let Some(replay) = &mut self.replay {
+                replay.record(inputs, &self.game);
+                if replay.inputs.len() >= MAX_REPLAY_TICKS {
+                    info!("Room {} stopped recording: game too long", self.id);
+                    self.replay = None;
+                }
+            }
+        }
+        self.broadcast(&self.game.get_state_msg());
+        self.acted.clear();
+        self.last_step = Instant::now();
+        for pid in &self.agents {
+            self.send_observation(*pid);
+        }
+    }
+
+    /// Starts a fresh round with the players still connected, keeping their
+    /// ids and resume tokens, and goes back to the lobby.
+    fn reset_for_next_round(&mut self) {
+        let mut players: Vec<(u8, String)> = self
+            .game
+            .players
+            .iter()
+            .filter(|p| self.clients.contains_key(&p.id) || self.bots.contains_key(&p.id))
+            .map(|p| (p.id, p.name.clone()))
+            .collect();
+        players.sort_by_key(|(id, _)| *id);
+
+        for pid in self.disconnected.keys().copied().collect::<Vec<_>>() {
+            self.remove_player(pid);
+        }
+        self.game = GameInstance::new(self.rules, rand::random());
+        for (id, name) in players {
+            match self.bots.get(&id) {
+                Some(difficulty) => self.game.add_bot(id, name, *difficulty),
+                None => self.game.add_player(id, name),
+            }
+        }
+        self.ready.clear();
+        self.intermission_until = None;
+        self.transition(RoomStatus::Lobby);
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            players: self.seat_count(),
+            max_players: self.max_players,
+            spectators: self.spectators.len(),
+            status: self.status,
+        }
+    }
+
+    /// Serializes `msg` once and queues it on every connected client and
+    /// spectator, holding it back from spectators for `spectator_delay`.
+    /// External bots get observations instead of game state.
+    pub fn broadcast(&mut self, msg: &ServerMsg) {
+        let started = Instant::now();
         let json = serde_json::to_string(msg).unwrap();
-        if let Some(sink) = self.sinks.get(&player_id) {
-            let mut s = sink.lock().await;
-            let _ = s.send(Message::Text(json)).await;
+        let snapshot = outbox::is_snapshot(msg);
+        let state = matches!(msg, ServerMsg::GameState { .. });
+        for (pid, outbox) in &self.clients {
+            if state && self.agents.contains(pid) {
+                continue;
+            }
+            outbox.send_json(json.clone(), snapshot);
+        }
+        // Who is watching gives nothing away, so that list is never delayed.
+        if self.spectator_delay.is_zero() || matches!(msg, ServerMsg::Spectators { .. }) {
+            for spectator in self.spectators.values() {
+                spectator.outbox.send_json(json.clone(), snapshot);
+            }
+        } else if !self.spectators.is_empty() {
+            self.spectator_feed.push_back(DelayedMsg {
+                due: Instant::now() + self.spectator_delay,
+                json,
+                snapshot,
+                view: matches!(
+                    msg,
+                    ServerMsg::Waiting { .. }
+                        | ServerMsg::GameStart { .. }
+                        | ServerMsg::GameOver { .. }
+                ),
+            });
+        }
+        METRICS.broadcast.observe(started.elapsed());
+    }
+
+    pub fn send_to(&self, player_id: u8, msg: &ServerMsg) {
+        if let Some(outbox) = self.clients.get(&player_id) {
+            outbox.send(msg);
         }
     }
 
-    pub fn remove_player(&mut self, player_id: u8) {
-        self.sinks.remove(&player_id);
-        if let Some(p) = self.game.players.iter_mut().find(|p| p.id == player_id) {
-            p.alive = false;
+    fn remove_player(&mut self, player_id: u8) {
+        self.clients.remove(&player_id);
+        self.disconnected.remove(&player_id);
+        self.connections.remove(&player_id);
+        self.ready.remove(&player_id);
+        self.agents.remove(&player_id);
+        self.sessions.retain(|_, pid| *pid != player_id);
+        if self.host == Some(player_id) {
+            self.host = self.clients.keys().min().copied();
         }
+        self.note_if_empty();
+        if matches!(self.status, RoomStatus::Lobby | RoomStatus::Countdown) {
+            self.game.remove_player(player_id);
+        } else if !self.game.is_bot(player_id) {
+            // A character a bot took over plays on until the round ends.
+            self.game.forfeit(player_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room() -> Room {
+        Room::new("r".into(), "Room".into(), None, MIN_PLAYERS, MAX_PLAYERS)
+    }
+
+    fn join(room: &mut Room, name: &str) -> Option<Seat> {
+        room.join(name.to_string(), false, Outbox::detached())
+    }
+
+    #[test]
+    fn player_ids_are_reused_across_many_joins() {
+        let mut room = room();
+        let first = join(&mut room, "a").unwrap();
+        for _ in 0..1000 {
+            let seat = join(&mut room, "b").unwrap();
+            assert_eq!(seat.player_id, 1);
+            room.leave_player(seat.player_id, seat.connection);
+        }
+        assert_eq!(first.player_id, 0);
+        assert_eq!(room.seat_count(), 1);
+    }
+
+    #[test]
+    fn join_takes_the_lowest_free_id() {
+        let mut room = room();
+        let seats: Vec<Seat> = (0..MAX_PLAYERS)
+            .map(|_| join(&mut room, "p").unwrap())
+            .collect();
+        assert!(join(&mut room, "full").is_none());
+
+        room.leave_player(seats[1].player_id, seats[1].connection);
+        assert_eq!(join(&mut room, "again").unwrap().player_id, 1);
+    }
+
+    #[test]
+    fn delayed_feed_is_only_kept_while_spectated() {
+        let mut room = room();
+        room.spectator_delay = Duration::from_secs(10);
+        join(&mut room, "a");
+        assert!(room.spectator_feed.is_empty());
+
+        let seat = room.spectate("s".into(), Outbox::detached()).unwrap();
+        join(&mut room, "b");
+        assert_eq!(room.spectator_feed.len(), 2);
+
+        room.remove_spectator(seat.connection);
+        join(&mut room, "c");
+        assert!(room.spectator_feed.is_empty());
+    }
+
+    #[test]
+    fn closing_sends_spectators_to_the_lobby() {
+        let mut room = room();
+        let seat = join(&mut room, "a").unwrap();
+        let mut watcher = room.spectate("s".into(), Outbox::detached()).unwrap();
+
+        // The last player leaves and the room closes on its own.
+        room.leave_player(seat.player_id, seat.connection);
+        assert!(room.transition(RoomStatus::Closed));
+        assert_eq!(watcher.evicted.try_recv(), Ok(()));
     }
 }
@@ -0,0 +1,240 @@
+use log::debug;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{self, Duration, Instant};
+
+use shared::constants::TICK_RATE_MS;
+use shared::protocol::{ClientMsg, RoomInfo, RoomStatus};
+use shared::rules::Rules;
+
+use crate::metrics::{self, METRICS};
+use crate::outbox::Outbox;
+use crate::room::{Room, Seat};
+
+const COMMAND_QUEUE: usize = 256;
+
+/// Requests from connection tasks to the task that owns a room.
+pub enum RoomCmd {
+    Join {
+        name: String,
+        agent: bool,
+        outbox: Outbox,
+        reply: oneshot::Sender<Option<Seat>>,
+    },
+    Resume {
+        token: String,
+        outbox: Outbox,
+        reply: oneshot::Sender<Option<Seat>>,
+    },
+    Spectate {
+        name: String,
+        outbox: Outbox,
+        reply: oneshot::Sender<Option<Seat>>,
+    },
+    /// A spectator left or their socket closed.
+    Unspectate {
+        connection: u64,
+    },
+    /// Asks for the state spectators see, as `GameState` JSON.
+    State {
+        reply: oneshot::Sender<Option<String>>,
+    },
+    /// Admin only: removes a player, as a host kick would.
+    Kick {
+        player_id: u8,
+    },
+    /// Admin only: sends everyone back to the lobby and closes the room.
+    Close {
+        reason: String,
+    },
+    /// Admin only: the rules for the next round.
+    SetRules {
+        rules: Rules,
+    },
+    Msg {
+        player_id: u8,
+        connection: u64,
+        msg: ClientMsg,
+    },
+    Leave {
+        player_id: u8,
+        connection: u64,
+    },
+    Disconnect {
+        player_id: u8,
+        connection: u64,
+    },
+}
+
+/// What the lobby needs to list and find a room, published by the room task
+/// after every command and tick so nobody has to ask it.
+#[derive(Clone)]
+pub struct RoomSummary {
+    pub info: RoomInfo,
+    pub join_code: Option<String>,
+    pub joinable: bool,
+    pub lockstep: bool,
+    pub tick: u64,
+    /// Ticks of this room that finished more than a tick interval late.
+    pub tick_overruns: u64,
+}
+
+/// Cheap, cloneable handle to a running room task.
+#[derive(Clone)]
+pub struct RoomHandle {
+    tx: mpsc::Sender<RoomCmd>,
+    summary: watch::Receiver<RoomSummary>,
+}
+
+impl RoomHandle {
+    /// Spawns the task that owns `room` and returns a handle to it.
+    pub fn spawn(room: Room) -> Self {
+        let (tx, rx) = mpsc::channel(COMMAND_QUEUE);
+        let (summary_tx, summary) = watch::channel(summarize(&room, 0));
+        tokio::spawn(run(room, rx, summary_tx));
+        RoomHandle { tx, summary }
+    }
+
+    pub fn summary(&self) -> RoomSummary {
+        self.summary.borrow().clone()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed() || self.summary.borrow().info.status == RoomStatus::Closed
+    }
+
+    pub async fn join(&self, name: String, agent: bool, outbox: Outbox) -> Option<Seat> {
+        let (reply, rx) = oneshot::channel();
+        self.send(RoomCmd::Join {
+            name,
+            agent,
+            outbox,
+            reply,
+        })
+        .await;
+        rx.await.ok().flatten()
+    }
+
+    pub async fn resume(&self, token: String, outbox: Outbox) -> Option<Seat> {
+        let (reply, rx) = oneshot::channel();
+        self.send(RoomCmd::Resume {
+            token,
+            outbox,
+            reply,
+        })
+        .await;
+        rx.await.ok().flatten()
+    }
+
+    pub async fn spectate(&self, name: String, outbox: Outbox) -> Option<Seat> {
+        let (reply, rx) = oneshot::channel();
+        self.send(RoomCmd::Spectate {
+            name,
+            outbox,
+            reply,
+        })
+        .await;
+        rx.await.ok().flatten()
+    }
+
+    /// The state spectators see, as `GameState` JSON.
+    pub async fn state(&self) -> Option<String> {
+        let (reply, rx) = oneshot::channel();
+        self.send(RoomCmd::State { reply }).await;
+        rx.await.ok().flatten()
+    }
+
+    /// Queues a command. Commands sent after the room has closed are dropped.
+    pub async fn send(&self, cmd: RoomCmd) {
+        let _ = self.tx.send(cmd).await;
+    }
+}
+
+fn summarize(room: &Room, tick_overruns: u64) -> RoomSummary {
+    RoomSummary {
+        info: room.info(),
+        join_code: room.join_code.clone(),
+        joinable: room.is_joinable(),
+        lockstep: room.lockstep,
+        tick: room.game.tick,
+        tick_overruns,
+    }
+}
+
+async fn run(mut room: Room, mut rx: mpsc::Receiver<RoomCmd>, summary: watch::Sender<RoomSummary>) {
+    let period = Duration::from_millis(TICK_RATE_MS);
+    let mut interval = time::interval(period);
+    let mut overruns = 0;
+
+    loop {
+        tokio::select! {
+            cmd = rx.recv() => match cmd {
+                Some(cmd) => apply(&mut room, cmd),
+                None => break,
+            },
+            due = interval.tick() => {
+                let started = Instant::now();
+                room.update();
+                METRICS.tick.observe(started.elapsed());
+                // Late either because the update was slow or because the
+                // task was not scheduled in time.
+                if due.elapsed() > period {
+                    overruns += 1;
+                    metrics::inc(&METRICS.tick_overruns);
+                }
+            }
+        }
+
+        summary.send_replace(summarize(&room, overruns));
+        if room.status() == RoomStatus::Closed {
+            break;
+        }
+    }
+    debug!("Room {} task stopped", room.id);
+}
+
+fn apply(room: &mut Room, cmd: RoomCmd) {
+    match cmd {
+        RoomCmd::Join {
+            name,
+            agent,
+            outbox,
+            reply,
+        } => {
+            let _ = reply.send(room.join(name, agent, outbox));
+        }
+        RoomCmd::Resume {
+            token,
+            outbox,
+            reply,
+        } => {
+            let _ = reply.send(room.resume(&token, outbox));
+        }
+        RoomCmd::Spectate {
+            name,
+            outbox,
+            reply,
+        } => {
+            let _ = reply.send(room.spectate(name, outbox));
+        }
+        RoomCmd::Unspectate { connection } => room.remove_spectator(connection),
+        RoomCmd::State { reply } => {
+            let _ = reply.send(room.state_json());
+        }
+        RoomCmd::Kick { player_id } => room.kick(player_id),
+        RoomCmd::Close { reason } => room.close(&reason),
+        RoomCmd::SetRules { rules } => room.set_rules(rules),
+        RoomCmd::Msg {
+            player_id,
+            connection,
+            msg,
+        } => room.handle_msg(player_id, connection, msg),
+        RoomCmd::Leave {
+            player_id,
+            connection,
+        } => room.leave_player(player_id, connection),
+        RoomCmd::Disconnect {
+            player_id,
+            connection,
+        } => room.disconnect_player(player_id, connection),
+    }
+}
@@ -0,0 +1,434 @@
+//! Serves the web client, from the copy built into the binary or from a
+//! directory that overrides it. Any file is reachable, typed by its
+//! extension, with validators for conditional requests and with a gzip or
+//! brotli copy sent instead to clients that accept one. A directory is served
+//! by its `index.html`, after a redirect to add a missing trailing slash.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use crate::embedded;
+use crate::http::Request;
+
+// Pre-compressed copies, best first: Content-Encoding and file suffix.
+const ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// A file picked to answer a request, in the encoding it will be sent in.
+struct Found {
+    content_type: &'static str,
+    body: Body,
+    len: u64,
+    encoding: Option<&'static str>,
+    /// Unquoted, and without the encoding's suffix.
+    etag: String,
+    /// Only known for files on disk.
+    modified: Option<SystemTime>,
+}
+
+enum Body {
+    File(File),
+    Embedded(&'static [u8]),
+}
+
+/// Answers a GET or HEAD for a file of the web client, from `dir` if given
+/// and from the embedded copy otherwise. Returns whether the connection can
+/// be kept open for another request.
+pub async fn serve(stream: &mut TcpStream, request: &Request, dir: Option<&Path>) -> bool {
+    let head_only = match request.method.as_str() {
+        "GET" => false,
+        "HEAD" => true,
+        _ => {
+            let body = "Method Not Allowed";
+            let head = format!(
+                "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET, HEAD\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(head.as_bytes()).await;
+            return false;
+        }
+    };
+    let keep_alive = request.keep_alive();
+
+    let path = resolve(request.path());
+    if let Some(path) = &path {
+        // Relative links in a directory's index only work from a URL that
+        // ends in a slash.
+        if !request.path().ends_with('/') && is_directory(dir, path).await {
+            return redirect(stream, &with_trailing_slash(&request.target), keep_alive).await;
+        }
+    }
+    let found = match path {
+        Some(path) => match dir {
+            Some(dir) => from_dir(request, dir, &path).await,
+            None => from_embedded(request, &path),
+        },
+        None => None,
+    };
+    let Some(found) = found else {
+        return not_found(stream, dir, head_only, keep_alive).await;
+    };
+
+    let etag = format!(
+        "\"{}{}\"",
+        found.etag,
+        found
+            .encoding
+            .map(|e| format!("-{}", e))
+            .unwrap_or_default()
+    );
+    let mut head = String::from("Vary: Accept-Encoding\r\n");
+    head += &format!("ETag: {}\r\n", etag);
+    if let Some(modified) = found.modified {
+        head += &format!("Last-Modified: {}\r\n", httpdate::fmt_http_date(modified));
+    }
+    // Asset names don't change between builds, so always revalidate.
+    head += "Cache-Control: no-cache\r\n";
+    head += connection(keep_alive);
+
+    if not_modified(request, &etag, found.modified) {
+        let head = format!("HTTP/1.1 304 Not Modified\r\n{}\r\n", head);
+        return stream.write_all(head.as_bytes()).await.is_ok() && keep_alive;
+    }
+
+    if let Some(encoding) = found.encoding {
+        head += &format!("Content-Encoding: {}\r\n", encoding);
+    }
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}\r\n",
+        found.content_type, found.len, head
+    );
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return false;
+    }
+    if !head_only {
+        let sent = match found.body {
+            Body::File(mut file) => tokio::io::copy(&mut file, stream).await.map(|_| ()),
+            Body::Embedded(bytes) => stream.write_all(bytes).await,
+        };
+        if sent.is_err() {
+            return false;
+        }
+    }
+    keep_alive
+}
+
+async fn from_dir(request: &Request, dir: &Path, path: &str) -> Option<Found> {
+    let mut file = dir.join(path);
+    if fs::metadata(&file).await.is_ok_and(|meta| meta.is_dir()) {
+        file.push("index.html");
+    }
+    let meta = fs::metadata(&file).await.ok()?;
+    if !meta.is_file() {
+        return None;
+    }
+    let content_type = content_type(&file);
+
+    // A stale sibling would serve an old build, so it must be at least as
+    // new as the file itself.
+    let mut served = (file.clone(), meta, None);
+    for (coding, suffix) in ENCODINGS {
+        if !accepts(request, coding) {
+            continue;
+        }
+        let mut name = file.clone().into_os_string();
+        name.push(format!(".{}", suffix));
+        let sibling = PathBuf::from(name);
+        if let Ok(meta) = fs::metadata(&sibling).await {
+            if meta.is_file() && modified(&meta) >= modified(&served.1) {
+                served = (sibling, meta, Some(coding));
+                break;
+            }
+        }
+    }
+    let (file, meta, encoding) = served;
+
+    let modified = modified(&meta);
+    let nanos = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Some(Found {
+        content_type,
+        body: Body::File(File::open(&file).await.ok()?),
+        len: meta.len(),
+        encoding,
+        etag: format!("{:x}-{:x}", meta.len(), nanos.as_nanos()),
+        modified: Some(modified),
+    })
+}
+
+fn from_embedded(request: &Request, path: &str) -> Option<Found> {
+    let asset = embedded::get(path).or_else(|| {
+        let index = if path.is_empty() {
+            "index.html".to_string()
+        } else {
+            format!("{}/index.html", path)
+        };
+        embedded::get(&index)
+    })?;
+    let (body, encoding) = match (asset.br, asset.gzip) {
+        (Some(br), _) if accepts(request, "br") => (br, Some("br")),
+        (_, Some(gzip)) if accepts(request, "gzip") => (gzip, Some("gzip")),
+        _ => (asset.body, None),
+    };
+    Some(Found {
+        content_type: content_type(Path::new(asset.path)),
+        body: Body::Embedded(body),
+        len: body.len() as u64,
+        encoding,
+        etag: asset.etag.to_string(),
+        modified: None,
+    })
+}
+
+async fn is_directory(dir: Option<&Path>, path: &str) -> bool {
+    match dir {
+        Some(dir) => fs::metadata(dir.join(path))
+            .await
+            .is_ok_and(|meta| meta.is_dir()),
+        None => {
+            !path.is_empty()
+                && embedded::get(path).is_none()
+                && embedded::get(&format!("{}/index.html", path)).is_some()
+        }
+    }
+}
+
+/// `target` with a slash added to the end of its path.
+fn with_trailing_slash(target: &str) -> String {
+    match target.split_once('?') {
+        Some((path, query)) => format!("{}/?{}", path, query),
+        None => format!("{}/", target),
+    }
+}
+
+/// Maps a URL path onto a `/`-separated path relative to the web root, or
+/// `None` for anything that could reach outside it or into hidden files:
+/// `..`, dot files, backslashes, drive letters and NUL bytes, encoded or not.
+fn resolve(url_path: &str) -> Option<String> {
+    let decoded = percent_decode(url_path)?;
+    let mut segments = Vec::new();
+    for segment in decoded.split('/') {
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        if segment.starts_with('.') || segment.contains(['\\', ':', '\0']) {
+            return None;
+        }
+        segments.push(segment);
+    }
+    Some(segments.join("/"))
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            // from_str_radix would also take a sign, as in `%+1`.
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            out.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "application/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "txt" => "text/plain; charset=utf-8",
+        "wasm" => "application/wasm",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "m4a" => "audio/mp4",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Whether `Accept-Encoding` allows `coding`, i.e. lists it without `q=0`.
+fn accepts(request: &Request, coding: &str) -> bool {
+    let Some(accept) = request.header("accept-encoding") else {
+        return false;
+    };
+    accept.split(',').any(|item| {
+        let mut parts = item.split(';').map(str::trim);
+        let name = parts.next().unwrap_or("");
+        let refused = parts.any(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        name.eq_ignore_ascii_case(coding) && !refused
+    })
+}
+
+// `If-None-Match` wins over `If-Modified-Since` when both are sent.
+fn not_modified(request: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(tags) = request.header("if-none-match") {
+        return tags
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag);
+    }
+    let since = request
+        .header("if-modified-since")
+        .and_then(|date| httpdate::parse_http_date(date).ok());
+    // Dates only have whole seconds.
+    let secs = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    };
+    match (modified, since) {
+        (Some(modified), Some(since)) => secs(modified) <= secs(since),
+        _ => false,
+    }
+}
+
+fn modified(meta: &std::fs::Metadata) -> SystemTime {
+    meta.modified().unwrap_or(UNIX_EPOCH)
+}
+
+fn connection(keep_alive: bool) -> &'static str {
+    if keep_alive {
+        "Connection: keep-alive\r\n"
+    } else {
+        "Connection: close\r\n"
+    }
+}
+
+async fn redirect(stream: &mut TcpStream, location: &str, keep_alive: bool) -> bool {
+    let head = format!(
+        "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\nContent-Length: 0\r\n{}\r\n",
+        location,
+        connection(keep_alive)
+    );
+    stream.write_all(head.as_bytes()).await.is_ok() && keep_alive
+}
+
+/// Answers with the web root's `404.html` if it has one.
+async fn not_found(
+    stream: &mut TcpStream,
+    dir: Option<&Path>,
+    head_only: bool,
+    keep_alive: bool,
+) -> bool {
+    let page = match dir {
+        Some(dir) => fs::read(dir.join("404.html")).await.ok(),
+        None => embedded::get("404.html").map(|asset| asset.body.to_vec()),
+    };
+    let (content_type, body) = match page {
+        Some(page) => ("text/html; charset=utf-8", page),
+        None => ("text/plain; charset=utf-8", b"Not Found".to_vec()),
+    };
+    let head = format!(
+        "HTTP/1.1 404 Not Found\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\n{}\r\n",
+        content_type,
+        body.len(),
+        connection(keep_alive)
+    );
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return false;
+    }
+    if !head_only && stream.write_all(&body).await.is_err() {
+        return false;
+    }
+    keep_alive
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_keeps_plain_paths() {
+        assert_eq!(resolve("/").as_deref(), Some(""));
+        assert_eq!(resolve("/index.html").as_deref(), Some("index.html"));
+        assert_eq!(
+            resolve("//assets/./app.js").as_deref(),
+            Some("assets/app.js")
+        );
+        assert_eq!(resolve("/a%20b/%2e/c").as_deref(), Some("a b/c"));
+    }
+
+    #[test]
+    fn resolve_refuses_parent_directories() {
+        for path in [
+            "/..",
+            "/../etc/passwd",
+            "/assets/../../secret",
+            "/%2e%2e/secret",
+            "/%2E%2e/secret",
+            "/.%2E/secret",
+            "/assets%2f..%2F..%2fsecret",
+        ] {
+            assert_eq!(resolve(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn resolve_refuses_backslashes_drives_and_nul() {
+        for path in [
+            "/..\\secret",
+            "/assets\\app.js",
+            "/%5c..%5Csecret",
+            "/C:/Windows",
+            "/c%3a/Windows",
+            "/index.html%00.png",
+            "/index.html\0",
+        ] {
+            assert_eq!(resolve(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn resolve_refuses_dot_files() {
+        for path in ["/.env", "/.git/config", "/assets/.hidden", "/%2egit/HEAD"] {
+            assert_eq!(resolve(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn percent_decode_refuses_malformed_escapes() {
+        assert_eq!(percent_decode("%41%4a%4A").as_deref(), Some("AJJ"));
+        for s in ["%", "%4", "a%", "%zz", "%4g", "%+1", "%-1", "%c3", "%ff"] {
+            assert_eq!(percent_decode(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn trailing_slash_goes_before_the_query() {
+        assert_eq!(with_trailing_slash("/docs"), "/docs/");
+        assert_eq!(with_trailing_slash("/docs?page=2"), "/docs/?page=2");
+    }
+}
@@ -7,12 +7,21 @@ pub const CANVAS_HEIGHT: u32 = TILE_SIZE * MAP_ROWS as u32;
 pub const TICK_RATE_MS: u64 = 50; // 20 ticks per second
 pub const BOMB_TIMER_TICKS: u32 = 40; // 2 seconds
 pub const EXPLOSION_DURATION_TICKS: u32 = 10; // 0.5 seconds
+pub const SPAWN_INVULNERABILITY_TICKS: u32 = 60; // 3 seconds
+pub const START_COUNTDOWN_SECS: u32 = 3;
 
-pub const DEFAULT_SPEED: f64 = 2.0;
-pub const SPEED_BOOST: f64 = 0.5;
+// Positions are fixed-point: SUBTILE units make one tile, so the server and
+// the WASM client compute bit-identical movement.
+pub const SUBTILE: i32 = 256;
+
+// Speeds are in SUBTILE units per tick.
+pub const DEFAULT_SPEED: i32 = 26; // ~2 tiles per second
+pub const SPEED_BOOST: i32 = 6; // ~0.5 tiles per second
+pub const DEFAULT_CORNER_TOLERANCE: i32 = 96; // ~0.4 tile
 pub const DEFAULT_BOMB_RANGE: u32 = 1;
 pub const DEFAULT_MAX_BOMBS: u32 = 1;
 
+pub const MIN_PLAYERS: usize = 2;
 pub const MAX_PLAYERS: usize = 4;
 
 pub const PLAYER_COLORS: [&str; 4] = ["#FF4444", "#4444FF", "#44BB44", "#FFAA00"];
@@ -1,3 +1,5 @@
 pub mod constants;
 pub mod map;
+pub mod physics;
 pub mod protocol;
+pub mod rules;
@@ -14,25 +14,24 @@ pub fn generate_map() -> GameMap {
     let mut map = [[Tile::Empty; MAP_COLS]; MAP_ROWS];
 
     // Hard blocks in a grid pattern (every other row/col, starting from 1,1)
-    for r in 0..MAP_ROWS {
-        for c in 0..MAP_COLS {
-            if r % 2 == 0 && c % 2 == 0 && r > 0 && c > 0 && r < MAP_ROWS - 1 && c < MAP_COLS - 1
-            {
-                map[r][c] = Tile::HardBlock;
+    for (r, row) in map.iter_mut().enumerate() {
+        for (c, tile) in row.iter_mut().enumerate() {
+            if r % 2 == 0 && c % 2 == 0 && r > 0 && c > 0 && r < MAP_ROWS - 1 && c < MAP_COLS - 1 {
+                *tile = Tile::HardBlock;
             }
         }
     }
 
     // Soft blocks fill most remaining spaces
-    for r in 0..MAP_ROWS {
-        for c in 0..MAP_COLS {
-            if map[r][c] != Tile::Empty {
+    for (r, row) in map.iter_mut().enumerate() {
+        for (c, tile) in row.iter_mut().enumerate() {
+            if *tile != Tile::Empty {
                 continue;
             }
             if is_spawn_safe_zone(r, c) {
                 continue;
             }
-            map[r][c] = Tile::SoftBlock;
+            *tile = Tile::SoftBlock;
         }
     }
 
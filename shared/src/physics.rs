@@ -0,0 +1,189 @@
+use crate::constants::*;
+use crate::map::Tile;
+
+// Movement and collision shared by the server simulation and client-side
+// prediction. Both sides must run exactly this code so that replaying
+// unacknowledged inputs lands the local player where the server will.
+// Everything is integer math on SUBTILE units.
+
+const COLLISION_MARGIN: i32 = 38; // ~0.15 tile
+
+/// The tile a fixed-point coordinate rounds to, e.g. where a bomb is dropped.
+pub fn tile_of(v: i32) -> usize {
+    ((v + SUBTILE / 2) / SUBTILE).max(0) as usize
+}
+
+/// A fixed-point coordinate in (fractional) tiles, for rendering.
+pub fn to_tiles(v: i32) -> f64 {
+    v as f64 / SUBTILE as f64
+}
+
+pub fn can_move_to<R: AsRef<[Tile]>>(map: &[R], x: i32, y: i32) -> bool {
+    let margin = COLLISION_MARGIN;
+    let corners = [
+        (x + margin, y + margin),
+        (x + SUBTILE - margin, y + margin),
+        (x + margin, y + SUBTILE - margin),
+        (x + SUBTILE - margin, y + SUBTILE - margin),
+    ];
+
+    for (cx, cy) in &corners {
+        if *cx < 0 || *cy < 0 {
+            return false;
+        }
+        let col = (cx / SUBTILE) as usize;
+        let row = (cy / SUBTILE) as usize;
+        let tile = map.get(row).and_then(|r| r.as_ref().get(col));
+        match tile {
+            None | Some(Tile::HardBlock) | Some(Tile::SoftBlock) => return false,
+            Some(Tile::Empty) => {}
+        }
+    }
+
+    true
+}
+
+fn is_open<R: AsRef<[Tile]>>(map: &[R], col: i32, row: i32) -> bool {
+    if col < 0 || row < 0 {
+        return false;
+    }
+    let tile = map
+        .get(row as usize)
+        .and_then(|r| r.as_ref().get(col as usize));
+    tile == Some(&Tile::Empty)
+}
+
+/// Advances a player by one tick of movement and returns the new position.
+/// `dx` and `dy` are directions in -1..=1; `speed` is per tick.
+///
+/// Moving along one axis into a corner, a player within `corner_tolerance` of
+/// an open lane's centre is nudged toward it instead of stopping. Diagonal
+/// input moves along whichever axes are open, without the assist.
+pub fn move_player<R: AsRef<[Tile]>>(
+    map: &[R],
+    x: i32,
+    y: i32,
+    dx: i8,
+    dy: i8,
+    speed: i32,
+    corner_tolerance: i32,
+) -> (i32, i32) {
+    let (dx, dy) = (dx.signum() as i32, dy.signum() as i32);
+    let (x, y) = match (dx, dy) {
+        (0, 0) => (x, y),
+        (_, 0) | (0, _) => slide(map, x, y, dx, dy, speed, corner_tolerance),
+        _ => {
+            let mut x = x;
+            let mut y = y;
+            if can_move_to(map, x + dx * speed, y) {
+                x += dx * speed;
+            }
+            if can_move_to(map, x, y + dy * speed) {
+                y += dy * speed;
+            }
+            (x, y)
+        }
+    };
+
+    (
+        x.clamp(0, (MAP_COLS as i32 - 1) * SUBTILE),
+        y.clamp(0, (MAP_ROWS as i32 - 1) * SUBTILE),
+    )
+}
+
+// One step along a single axis, falling back to the corner assist.
+fn slide<R: AsRef<[Tile]>>(
+    map: &[R],
+    x: i32,
+    y: i32,
+    dx: i32,
+    dy: i32,
+    speed: i32,
+    tolerance: i32,
+) -> (i32, i32) {
+    if can_move_to(map, x + dx * speed, y + dy * speed) {
+        return (x + dx * speed, y + dy * speed);
+    }
+
+    // Blocked: if the tile ahead in the lane the player is nearest to is
+    // open, move across the lane toward its centre.
+    let col = tile_of(x) as i32;
+    let row = tile_of(y) as i32;
+    if !is_open(map, col + dx, row + dy) {
+        return (x, y);
+    }
+    let offset = if dx != 0 {
+        y - row * SUBTILE
+    } else {
+        x - col * SUBTILE
+    };
+    if offset == 0 || offset.abs() > tolerance {
+        return (x, y);
+    }
+    let nudge = offset.signum() * offset.abs().min(speed);
+    let (nx, ny) = if dx != 0 {
+        (x, y - nudge)
+    } else {
+        (x - nudge, y)
+    };
+    if can_move_to(map, nx, ny) {
+        (nx, ny)
+    } else {
+        (x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Tile::{Empty as E, HardBlock as H};
+
+    const SPEED: i32 = DEFAULT_SPEED;
+    const TOLERANCE: i32 = DEFAULT_CORNER_TOLERANCE;
+
+    // An open top row with one lane leading down from its middle tile.
+    const LANE: [[Tile; 3]; 2] = [[E, E, E], [H, E, H]];
+
+    // Close enough to the second row that one more step reaches it.
+    const Y: i32 = 20;
+
+    // Moves down toward the lane from `offset` right of its centre.
+    fn down(map: &[[Tile; 3]], offset: i32, tolerance: i32) -> (i32, i32) {
+        move_player(map, SUBTILE + offset, Y, 0, 1, SPEED, tolerance)
+    }
+
+    #[test]
+    fn no_assist_with_zero_tolerance() {
+        assert_eq!(down(&LANE, 60, 0), (SUBTILE + 60, Y));
+    }
+
+    #[test]
+    fn offset_inside_tolerance_is_nudged_into_the_lane() {
+        assert_eq!(down(&LANE, 60, TOLERANCE), (SUBTILE + 60 - SPEED, Y));
+        assert_eq!(down(&LANE, -60, TOLERANCE), (SUBTILE - 60 + SPEED, Y));
+    }
+
+    #[test]
+    fn holding_the_direction_slides_through_the_corner() {
+        let (mut x, mut y) = (SUBTILE + 60, Y);
+        for _ in 0..10 {
+            (x, y) = move_player(&LANE, x, y, 0, 1, SPEED, TOLERANCE);
+        }
+        assert_eq!((tile_of(x), tile_of(y)), (1, 1));
+    }
+
+    #[test]
+    fn offset_outside_tolerance_stays_blocked() {
+        assert_eq!(
+            down(&LANE, TOLERANCE + 1, TOLERANCE),
+            (SUBTILE + TOLERANCE + 1, Y)
+        );
+    }
+
+    #[test]
+    fn blocked_lane_gives_no_assist() {
+        let walled = [[E, E, E], [H, H, H]];
+        assert_eq!(down(&walled, 60, TOLERANCE), (SUBTILE + 60, Y));
+    }
+}
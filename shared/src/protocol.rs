@@ -1,13 +1,83 @@
 use crate::map::Tile;
+use crate::rules::Rules;
 use serde::{Deserialize, Serialize};
 
 // Client -> Server messages
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ClientMsg {
-    Join { name: String },
-    Move { dx: f64, dy: f64 },
+    /// Enters the lobby under `name`.
+    Join {
+        name: String,
+    },
+    /// Enters the lobby as an external bot. In a game the connection gets an
+    /// `Observation` every tick instead of `GameState`, and plays with `Act`.
+    JoinAsBot {
+        name: String,
+    },
+    /// Reattaches a new connection to the player that `token` was issued for.
+    Resume {
+        token: String,
+    },
+    ListRooms,
+    CreateRoom {
+        name: String,
+        private: bool,
+        min_players: Option<usize>,
+        max_players: Option<usize>,
+        /// Tournament mode: each tick waits until every external bot in the
+        /// room has acted on the previous observation.
+        #[serde(default)]
+        lockstep: bool,
+        /// Seconds the spectator feed lags behind the game. Can only raise
+        /// the server's delay, up to its maximum.
+        #[serde(default)]
+        spectator_delay_secs: Option<u32>,
+    },
+    JoinRoom {
+        room_id: String,
+    },
+    JoinByCode {
+        code: String,
+    },
+    /// Joins any open public room, creating one if there is none.
+    QuickJoin,
+    /// Watches a public room without a seat: the connection gets the room's
+    /// messages, in any state, but cannot play. Leave with `LeaveRoom`.
+    Spectate {
+        room_id: String,
+    },
+    /// Leaves the current room and returns to the lobby.
+    LeaveRoom,
+    SetReady {
+        ready: bool,
+    },
+    /// Host only: starts the countdown once everyone else is ready.
+    StartGame,
+    /// Host only: removes a player from the room.
+    Kick {
+        player_id: u8,
+    },
+    /// Host only: fills a free seat with a computer player.
+    AddBot {
+        difficulty: BotDifficulty,
+    },
+    /// One tick of movement input, each axis in -1..=1. `seq` increases by
+    /// one per input so the server can report which inputs it has applied.
+    Move {
+        dx: i8,
+        dy: i8,
+        seq: u32,
+    },
     PlaceBomb,
+    /// External bots only: the action to take on the tick after `tick`, the
+    /// tick of the observation it answers. An action for any other tick is
+    /// dropped. If no action arrives in time the previous direction is held,
+    /// as with `Move`.
+    Act {
+        tick: u64,
+        action: BotAction,
+    },
 }
 
 // Server -> Client messages
@@ -17,6 +87,29 @@ pub enum ServerMsg {
     Welcome {
         player_id: u8,
         room_id: String,
+        room_name: String,
+        /// Set for private rooms so the players can share it.
+        join_code: Option<String>,
+        resume_token: String,
+    },
+    ResumeRejected,
+    /// Sent instead of `Welcome` to a connection that started spectating.
+    Spectating {
+        room_id: String,
+        room_name: String,
+        /// How many seconds behind the game the spectator feed runs.
+        #[serde(default)]
+        delay_secs: u32,
+    },
+    /// Everyone watching the room, sent whenever that changes.
+    Spectators {
+        names: Vec<String>,
+    },
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+    LobbyError {
+        message: String,
     },
     GameState {
         players: Vec<PlayerState>,
@@ -29,6 +122,7 @@ pub enum ServerMsg {
     GameStart {
         map: Vec<Vec<Tile>>,
         players: Vec<PlayerState>,
+        rules: Rules,
     },
     GameOver {
         winner: Option<u8>,
@@ -36,20 +130,95 @@ pub enum ServerMsg {
     Waiting {
         player_count: usize,
         need: usize,
+        max_players: usize,
+        host: Option<u8>,
+        players: Vec<RoomMember>,
+    },
+    /// Sent to external bots after every tick; see `Observation`.
+    Observation(Observation),
+    Countdown {
+        seconds_left: u32,
     },
+    /// Sent to a player the host removed, just before they return to the lobby.
+    Kicked,
+    /// A message from the server's operators, shown to the player whatever
+    /// screen they are on.
+    Notice {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RoomStatus {
+    /// Players gather and ready up.
+    Lobby,
+    /// The start countdown is running.
+    Countdown,
+    Playing,
+    /// A round just ended; the room returns to `Lobby` shortly.
+    Intermission,
+    /// The room is empty or shut down and about to be removed.
+    Closed,
+}
+
+impl RoomStatus {
+    pub fn can_transition_to(self, next: RoomStatus) -> bool {
+        use RoomStatus::*;
+        matches!(
+            (self, next),
+            (Lobby, Countdown)
+                | (Countdown, Lobby)
+                | (Countdown, Playing)
+                | (Playing, Intermission)
+                | (Intermission, Lobby)
+                | (Lobby | Countdown | Playing | Intermission, Closed)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub id: String,
+    pub name: String,
+    pub players: usize,
+    pub max_players: usize,
+    #[serde(default)]
+    pub spectators: usize,
+    pub status: RoomStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomMember {
+    pub id: u8,
+    pub name: String,
+    pub ready: bool,
+    /// Set for computer players.
+    pub bot: Option<BotDifficulty>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BotDifficulty {
+    Easy,
+    Medium,
+    Hard,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayerState {
     pub id: u8,
     pub name: String,
-    pub x: f64,
-    pub y: f64,
+    /// Position in `SUBTILE` units.
+    pub x: i32,
+    pub y: i32,
     pub alive: bool,
-    pub speed: f64,
+    /// `SUBTILE` units per tick.
+    pub speed: i32,
     pub bomb_range: u32,
     pub max_bombs: u32,
     pub color_index: u8,
+    pub invulnerable: bool,
+    /// Sequence number of the last `ClientMsg::Move` applied for this player.
+    pub last_seq: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -80,3 +249,89 @@ pub struct ItemState {
     pub y: usize,
     pub kind: ItemKind,
 }
+
+/// A discrete action for an external bot. `Bomb` drops a bomb and stands
+/// still.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BotAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Stay,
+    Bomb,
+}
+
+impl BotAction {
+    /// Movement direction as `(dx, dy)`.
+    pub fn direction(self) -> (i8, i8) {
+        match self {
+            BotAction::Up => (0, -1),
+            BotAction::Down => (0, 1),
+            BotAction::Left => (-1, 0),
+            BotAction::Right => (1, 0),
+            BotAction::Stay | BotAction::Bomb => (0, 0),
+        }
+    }
+}
+
+/// What an external bot sees each tick, simplified from `GameState`.
+///
+/// `grid` and `danger` are indexed `[y][x]` with `y` growing downward. Other
+/// players, bombs and items are given relative to the bot's own tile: `dx`
+/// grows to the right, `dy` downward.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Observation {
+    pub tick: u64,
+    /// One string per map row: `.` open, `#` hard block, `+` soft block.
+    pub grid: Vec<String>,
+    /// Ticks until each tile is next on fire (0 while burning), following
+    /// chain reactions, or `null` if nothing threatens it.
+    pub danger: Vec<Vec<Option<u32>>>,
+    pub me: BotSelf,
+    /// Everyone else, alive or not.
+    pub players: Vec<BotEntity>,
+    pub bombs: Vec<BotBomb>,
+    pub items: Vec<BotItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotSelf {
+    pub id: u8,
+    /// The tile the bot counts as standing on.
+    pub x: usize,
+    pub y: usize,
+    /// How far off that tile's centre the bot is, in `SUBTILE` units.
+    pub offset_x: i32,
+    pub offset_y: i32,
+    pub alive: bool,
+    /// Bombs the bot can still place right now.
+    pub bombs_left: u32,
+    pub bomb_range: u32,
+    /// `SUBTILE` units per tick.
+    pub speed: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotEntity {
+    pub id: u8,
+    pub dx: i32,
+    pub dy: i32,
+    pub alive: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotBomb {
+    pub dx: i32,
+    pub dy: i32,
+    /// Ticks until it explodes.
+    pub timer: u32,
+    pub range: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotItem {
+    pub dx: i32,
+    pub dy: i32,
+    pub kind: ItemKind,
+}
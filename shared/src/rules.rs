@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+use crate::constants::*;
+
+/// Per-room gameplay settings. Sent with `GameStart` so client-side prediction
+/// moves exactly like the server.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    /// How far off a lane's centre, in `SUBTILE` units, a player blocked by a
+    /// corner may be and still get nudged into the lane. 0 disables the assist.
+    pub corner_tolerance: i32,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            corner_tolerance: DEFAULT_CORNER_TOLERANCE,
+        }
+    }
+}
+
+impl Rules {
+    /// Checks every setting is in range, naming the first one that isn't.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0..=SUBTILE / 2).contains(&self.corner_tolerance) {
+            return Err(format!(
+                "corner_tolerance must be between 0 and {}, not {}",
+                SUBTILE / 2,
+                self.corner_tolerance
+            ));
+        }
+        Ok(())
+    }
+}
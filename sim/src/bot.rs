@@ -0,0 +1,621 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use shared::constants::*;
+use shared::map::{GameMap, Tile};
+use shared::physics;
+use shared::protocol::BotDifficulty;
+
+// Bots treat a tile as unsafe this many ticks either side of its blast, to
+// allow for the time spent walking between tile centres.
+const SAFETY_TICKS: u32 = 4;
+// How far off a lane's centre a bot may be before it straightens up.
+const ALIGN: i32 = 24;
+
+pub type Grid<T> = [[T; MAP_COLS]; MAP_ROWS];
+type TilePos = (usize, usize);
+
+/// When a tile burns: ticks until the fire arrives and until it goes out.
+#[derive(Clone, Copy)]
+struct Fire {
+    from: u32,
+    until: u32,
+}
+
+/// The quickest safe way to a tile.
+#[derive(Clone, Copy)]
+struct Route {
+    /// Ticks until the bot gets there, including any waiting.
+    ticks: u32,
+    /// Tile to step onto first.
+    first: TilePos,
+    /// Ticks to stay put before taking that step, for fire to burn out.
+    wait: u32,
+}
+
+/// A bomb as the bots see it.
+pub struct BombView {
+    pub x: usize,
+    pub y: usize,
+    pub timer: u32,
+    pub range: u32,
+}
+
+/// A player as the bots see it.
+pub struct PlayerView {
+    pub id: u8,
+    pub x: i32,
+    pub y: i32,
+    pub alive: bool,
+    pub speed: i32,
+    pub bomb_range: u32,
+    pub can_bomb: bool,
+}
+
+/// Everything a bot looks at to decide its next move.
+pub struct World<'a> {
+    pub map: &'a GameMap,
+    pub bombs: Vec<BombView>,
+    /// Tiles on fire right now, with the ticks until each goes out.
+    pub burning: Vec<(TilePos, u32)>,
+    pub items: Vec<TilePos>,
+    pub players: Vec<PlayerView>,
+}
+
+// How each difficulty plays.
+struct Profile {
+    // Ticks between decisions; steering still happens every tick.
+    think_every: u32,
+    // Chance of acting on a good bomb spot when one is found.
+    bomb_chance: f64,
+    hunt: bool,
+    trap: bool,
+    // Times its first step from where it stands rather than a tile centre.
+    precise: bool,
+    // Plans to wait for fire to burn out rather than only running from it.
+    patient: bool,
+    // Keeps to tiles it could escape from if an opponent bombed right away.
+    wary: bool,
+    item_radius: u32,
+}
+
+fn profile(difficulty: BotDifficulty) -> Profile {
+    match difficulty {
+        BotDifficulty::Easy => Profile {
+            think_every: 8,
+            bomb_chance: 0.4,
+            hunt: false,
+            trap: false,
+            precise: false,
+            patient: false,
+            wary: false,
+            item_radius: 3,
+        },
+        BotDifficulty::Medium => Profile {
+            think_every: 4,
+            bomb_chance: 0.8,
+            hunt: false,
+            trap: false,
+            precise: true,
+            patient: false,
+            wary: false,
+            item_radius: 6,
+        },
+        BotDifficulty::Hard => Profile {
+            think_every: 2,
+            bomb_chance: 0.9,
+            hunt: true,
+            trap: true,
+            precise: true,
+            patient: true,
+            wary: true,
+            item_radius: 10,
+        },
+    }
+}
+
+/// A computer-controlled player. Produces one tick of input at a time, the
+/// same shape a client sends, so the simulation treats it like anyone else.
+#[derive(Clone)]
+pub struct Bot {
+    pub player_id: u8,
+    pub difficulty: BotDifficulty,
+    rng: StdRng,
+    // Tile the bot is heading for; the path there is re-planned every tick.
+    target: Option<TilePos>,
+    wait: u32,
+}
+
+impl Bot {
+    /// `seed` drives the bot's random choices, so the same seed and world
+    /// give the same play.
+    pub fn new(player_id: u8, difficulty: BotDifficulty, seed: u64) -> Self {
+        Bot {
+            player_id,
+            difficulty,
+            rng: StdRng::seed_from_u64(seed),
+            target: None,
+            wait: 0,
+        }
+    }
+
+    /// Decides this tick's direction and whether to drop a bomb.
+    pub fn next_input(&mut self, world: &World) -> (i8, i8, bool) {
+        let Some(me) = world.players.iter().find(|p| p.id == self.player_id) else {
+            return (0, 0, false);
+        };
+        if !me.alive {
+            return (0, 0, false);
+        }
+
+        let danger = fire_map(world, &[]);
+        let paths = search(world.map, me, &danger, &profile(self.difficulty));
+
+        let mut bomb = false;
+        if self.wait == 0 {
+            self.wait = profile(self.difficulty).think_every;
+            bomb = self.think(world, me, &danger, &paths);
+        }
+        self.wait -= 1;
+
+        let Some(target) = self.target else {
+            return (0, 0, bomb);
+        };
+        let Some(route) = paths[target.1][target.0] else {
+            self.target = None;
+            return (0, 0, bomb);
+        };
+        if route.wait > 0 {
+            return (0, 0, bomb);
+        }
+        match steer(me.x, me.y, route.first, me.speed) {
+            Some((dx, dy)) => (dx, dy, bomb),
+            None => {
+                self.target = None;
+                (0, 0, bomb)
+            }
+        }
+    }
+
+    // Picks where to head next. Returns whether to bomb right here.
+    fn think(
+        &mut self,
+        world: &World,
+        me: &PlayerView,
+        danger: &Grid<Option<Fire>>,
+        paths: &Grid<Option<Route>>,
+    ) -> bool {
+        let profile = profile(self.difficulty);
+        let here = tile(me);
+        let is_safe = |pos: TilePos| danger[pos.1][pos.0].is_none();
+        let pace = ticks_per_tile(me.speed);
+        let within = |pos: TilePos, radius: u32| {
+            paths[pos.1][pos.0].is_some_and(|route| route.ticks <= radius * pace)
+        };
+        let cover = profile.wary.then(|| cover_map(world, me));
+        let is_covered = |pos: TilePos| cover.as_ref().is_none_or(|c| c[pos.1][pos.0]);
+        let is_good = |pos: TilePos| is_safe(pos) && is_covered(pos);
+
+        // Get out of any blast first, sticking to the way already chosen so
+        // the bot doesn't dither between two equally good ones.
+        if !is_safe(here) {
+            // A spare bomb dropped on the way out can close a trap.
+            if profile.trap && me.can_bomb && traps_anyone(world, me, here) {
+                if let Some(escape) = escape_after_bomb(world, me, here, &profile) {
+                    self.target = Some(escape);
+                    return true;
+                }
+            }
+            let keep = self
+                .target
+                .filter(|&pos| is_good(pos) && paths[pos.1][pos.0].is_some());
+            self.target = keep
+                .or_else(|| nearest(paths, is_good))
+                .or_else(|| nearest(paths, is_safe))
+                .or_else(|| latest_blast(paths, danger));
+            return false;
+        }
+
+        if me.can_bomb && self.rng.gen_bool(profile.bomb_chance) {
+            let worth = bomb_value(world, me, here, &profile);
+            if worth > 0 {
+                if let Some(escape) = escape_after_bomb(world, me, here, &profile) {
+                    self.target = Some(escape);
+                    return true;
+                }
+            }
+        }
+
+        let item = nearest(paths, |pos| {
+            is_good(pos) && within(pos, profile.item_radius) && world.items.contains(&pos)
+        });
+        let opponent = || {
+            nearest(paths, |pos| {
+                is_safe(pos)
+                    && world.players.iter().any(|p| {
+                        p.alive && p.id != me.id && in_blast(world.map, pos, tile(p), me.bomb_range)
+                    })
+            })
+        };
+        let farm = || {
+            nearest(paths, |pos| {
+                is_good(pos) && soft_blocks_hit(world.map, pos, me.bomb_range) > 0
+            })
+        };
+        // A spot close by where a bomb would leave someone no way out.
+        let ambush = || {
+            if !profile.trap || !me.can_bomb {
+                return None;
+            }
+            nearest(paths, |pos| {
+                is_good(pos) && within(pos, 2) && traps_anyone(world, me, pos)
+            })
+        };
+        let shelter = || nearest(paths, is_good).filter(|_| !is_covered(here));
+
+        self.target = if profile.hunt {
+            ambush().or(item).or_else(opponent).or_else(farm)
+        } else {
+            ambush().or(item).or_else(farm).or_else(opponent)
+        }
+        .or_else(shelter);
+        if self.target.is_none() && self.difficulty == BotDifficulty::Easy {
+            // Nothing to do: wander somewhere safe nearby.
+            let choices: Vec<TilePos> = tiles()
+                .filter(|pos| is_safe(*pos) && within(*pos, 3))
+                .collect();
+            if !choices.is_empty() {
+                self.target = Some(choices[self.rng.gen_range(0..choices.len())]);
+            }
+        }
+        false
+    }
+}
+
+fn tile(p: &PlayerView) -> TilePos {
+    (physics::tile_of(p.x), physics::tile_of(p.y))
+}
+
+fn tiles() -> impl Iterator<Item = TilePos> {
+    (0..MAP_ROWS).flat_map(|y| (0..MAP_COLS).map(move |x| (x, y)))
+}
+
+fn ticks_per_tile(speed: i32) -> u32 {
+    ((SUBTILE + speed - 1) / speed.max(1)) as u32
+}
+
+/// Tiles a bomb at `from` would set on fire, including `from`.
+fn blast(map: &GameMap, from: TilePos, range: u32) -> Vec<TilePos> {
+    let mut tiles = vec![from];
+    for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+        for i in 1..=range as i32 {
+            let x = from.0 as i32 + dx * i;
+            let y = from.1 as i32 + dy * i;
+            if x < 0 || y < 0 || x >= MAP_COLS as i32 || y >= MAP_ROWS as i32 {
+                break;
+            }
+            let pos = (x as usize, y as usize);
+            match map[pos.1][pos.0] {
+                Tile::HardBlock => break,
+                Tile::SoftBlock => {
+                    tiles.push(pos);
+                    break;
+                }
+                Tile::Empty => tiles.push(pos),
+            }
+        }
+    }
+    tiles
+}
+
+fn in_blast(map: &GameMap, from: TilePos, target: TilePos, range: u32) -> bool {
+    blast(map, from, range).contains(&target)
+}
+
+fn soft_blocks_hit(map: &GameMap, from: TilePos, range: u32) -> usize {
+    blast(map, from, range)
+        .iter()
+        .filter(|pos| map[pos.1][pos.0] == Tile::SoftBlock)
+        .count()
+}
+
+/// Ticks until each tile next burns, following chain reactions. `extra` adds
+/// a bomb that hasn't been placed yet.
+pub fn danger_map(world: &World, extra: Option<BombView>) -> Grid<Option<u32>> {
+    fire_map(world, extra.as_slice()).map(|row| row.map(|fire| fire.map(|f| f.from)))
+}
+
+// When each tile burns, following chain reactions. A tile in more than one
+// blast is treated as burning from the first until the last goes out.
+fn fire_map(world: &World, extra: &[BombView]) -> Grid<Option<Fire>> {
+    let mut bombs: Vec<&BombView> = world.bombs.iter().collect();
+    bombs.extend(extra);
+    let blasts: Vec<Vec<TilePos>> = bombs
+        .iter()
+        .map(|b| blast(world.map, (b.x, b.y), b.range))
+        .collect();
+
+    // A bomb caught in another's blast goes off with it.
+    let mut timers: Vec<u32> = bombs.iter().map(|b| b.timer).collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in 0..bombs.len() {
+            for j in 0..bombs.len() {
+                if timers[j] < timers[i] && blasts[j].contains(&(bombs[i].x, bombs[i].y)) {
+                    timers[i] = timers[j];
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut danger = [[None; MAP_COLS]; MAP_ROWS];
+    let mut add = |(x, y): TilePos, fire: Fire| {
+        let cell: &mut Option<Fire> = &mut danger[y][x];
+        *cell = Some(cell.map_or(fire, |f| Fire {
+            from: f.from.min(fire.from),
+            until: f.until.max(fire.until),
+        }));
+    };
+    for (tiles, &timer) in blasts.iter().zip(&timers) {
+        for &pos in tiles {
+            let until = timer + EXPLOSION_DURATION_TICKS;
+            add(pos, Fire { from: timer, until });
+        }
+    }
+    for &(pos, until) in &world.burning {
+        add(pos, Fire { from: 0, until });
+    }
+    danger
+}
+
+// Whether walking onto a tile `arrival` ticks from now is safe.
+fn safe_at(danger: Option<Fire>, arrival: u32) -> bool {
+    match danger {
+        None => true,
+        Some(f) => arrival + SAFETY_TICKS < f.from || arrival > f.until + SAFETY_TICKS,
+    }
+}
+
+// The last tick a bot that got onto a tile at `arrival` can stay there.
+fn stay_until(danger: Option<Fire>, arrival: u32) -> u32 {
+    match danger {
+        Some(f) if arrival + SAFETY_TICKS < f.from => f.from - SAFETY_TICKS - 1,
+        Some(_) if safe_at(danger, arrival) => u32::MAX,
+        Some(_) => arrival,
+        None => u32::MAX,
+    }
+}
+
+/// Earliest-arrival search over open tiles that won't be burning when `who`
+/// gets there. A patient searcher may wait on a safe tile for fire ahead to
+/// burn out; a precise one times the first step from where `who` stands, so
+/// a move already under way keeps its head start.
+fn search(
+    map: &GameMap,
+    who: &PlayerView,
+    danger: &Grid<Option<Fire>>,
+    skill: &Profile,
+) -> Grid<Option<Route>> {
+    let start = tile(who);
+    let pace = ticks_per_tile(who.speed);
+    let mut paths: Grid<Option<Route>> = [[None; MAP_COLS]; MAP_ROWS];
+    paths[start.1][start.0] = Some(Route {
+        ticks: 0,
+        first: start,
+        wait: 0,
+    });
+    let mut queue = BinaryHeap::from([Reverse((0, start))]);
+
+    while let Some(Reverse((ticks, pos))) = queue.pop() {
+        let route = paths[pos.1][pos.0].unwrap();
+        if route.ticks < ticks {
+            continue;
+        }
+        let last = stay_until(danger[pos.1][pos.0], ticks);
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let x = pos.0 as i32 + dx;
+            let y = pos.1 as i32 + dy;
+            if x < 0 || y < 0 || x >= MAP_COLS as i32 || y >= MAP_ROWS as i32 {
+                continue;
+            }
+            let next = (x as usize, y as usize);
+            if map[next.1][next.0] != Tile::Empty {
+                continue;
+            }
+            let step = if pos == start && skill.precise {
+                let off = (next.0 as i32 * SUBTILE - who.x).abs()
+                    + (next.1 as i32 * SUBTILE - who.y).abs();
+                ((off + who.speed - 1) / who.speed.max(1)) as u32
+            } else {
+                pace
+            };
+            let fire = danger[next.1][next.0];
+            let leave = match fire {
+                Some(f) if !safe_at(fire, ticks + step) => {
+                    (f.until + SAFETY_TICKS + 1).saturating_sub(step)
+                }
+                _ => ticks,
+            };
+            if leave > last || (leave > ticks && !skill.patient) {
+                continue;
+            }
+            let arrival = leave + step;
+            if paths[next.1][next.0].is_some_and(|r| r.ticks <= arrival) {
+                continue;
+            }
+            paths[next.1][next.0] = Some(if pos == start {
+                Route {
+                    ticks: arrival,
+                    first: next,
+                    wait: leave,
+                }
+            } else {
+                Route {
+                    ticks: arrival,
+                    ..route
+                }
+            });
+            queue.push(Reverse((arrival, next)));
+        }
+    }
+    paths
+}
+
+// The soonest reached tile matching `wanted`, ties broken by scan order so
+// the choice is deterministic.
+fn nearest(paths: &Grid<Option<Route>>, wanted: impl Fn(TilePos) -> bool) -> Option<TilePos> {
+    tiles()
+        .filter(|pos| paths[pos.1][pos.0].is_some() && wanted(*pos))
+        .min_by_key(|pos| paths[pos.1][pos.0].unwrap().ticks)
+}
+
+// With nowhere safe in reach, the reachable tile that burns last.
+fn latest_blast(paths: &Grid<Option<Route>>, danger: &Grid<Option<Fire>>) -> Option<TilePos> {
+    tiles()
+        .filter(|pos| paths[pos.1][pos.0].is_some())
+        .max_by_key(|pos| danger[pos.1][pos.0].map_or(u32::MAX, |f| f.from))
+}
+
+/// How worthwhile a bomb at `here` is: soft blocks it clears, opponents it
+/// threatens, and (for bots that trap) opponents it leaves with no way out.
+fn bomb_value(world: &World, me: &PlayerView, here: TilePos, profile: &Profile) -> u32 {
+    let mut value = soft_blocks_hit(world.map, here, me.bomb_range) as u32;
+    for p in world.players.iter().filter(|p| p.alive && p.id != me.id) {
+        if in_blast(world.map, here, tile(p), me.bomb_range) {
+            value += 3;
+        }
+        if profile.trap && traps(world, here, me.bomb_range, p) {
+            value += 10;
+        }
+    }
+    value
+}
+
+fn traps_anyone(world: &World, me: &PlayerView, here: TilePos) -> bool {
+    world
+        .players
+        .iter()
+        .any(|p| p.alive && p.id != me.id && traps(world, here, me.bomb_range, p))
+}
+
+/// Whether a bomb at `here` would leave `p` nowhere safe to run to.
+fn traps(world: &World, here: TilePos, range: u32, p: &PlayerView) -> bool {
+    if manhattan(here, tile(p)) > range + 2 {
+        return false;
+    }
+    let bomb = BombView {
+        x: here.0,
+        y: here.1,
+        timer: BOMB_TIMER_TICKS,
+        range,
+    };
+    let danger = fire_map(world, &[bomb]);
+    // Assume they get away if anyone could.
+    let paths = search(world.map, p, &danger, &profile(BotDifficulty::Hard));
+    nearest(&paths, |pos| danger[pos.1][pos.0].is_none()).is_none()
+}
+
+/// Whether each tile is one a bot could get clear of in time if every
+/// opponent dropped a bomb where they stand now.
+fn cover_map(world: &World, me: &PlayerView) -> Grid<bool> {
+    let threats: Vec<BombView> = world
+        .players
+        .iter()
+        .filter(|p| p.alive && p.id != me.id && p.can_bomb)
+        .map(|p| {
+            let (x, y) = tile(p);
+            BombView {
+                x,
+                y,
+                timer: BOMB_TIMER_TICKS,
+                range: p.bomb_range,
+            }
+        })
+        .collect();
+    let danger = fire_map(world, &threats);
+
+    // Steps from each open tile to the nearest one the fire won't reach.
+    let mut steps: Grid<Option<u32>> = [[None; MAP_COLS]; MAP_ROWS];
+    let mut queue = VecDeque::new();
+    for (x, y) in tiles() {
+        if world.map[y][x] == Tile::Empty && danger[y][x].is_none() {
+            steps[y][x] = Some(0);
+            queue.push_back((x, y));
+        }
+    }
+    while let Some(pos) = queue.pop_front() {
+        let n = steps[pos.1][pos.0].unwrap();
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let x = pos.0 as i32 + dx;
+            let y = pos.1 as i32 + dy;
+            if x < 0 || y < 0 || x >= MAP_COLS as i32 || y >= MAP_ROWS as i32 {
+                continue;
+            }
+            let (x, y) = (x as usize, y as usize);
+            if world.map[y][x] == Tile::Empty && steps[y][x].is_none() {
+                steps[y][x] = Some(n + 1);
+                queue.push_back((x, y));
+            }
+        }
+    }
+
+    let pace = ticks_per_tile(me.speed);
+    let mut cover = [[false; MAP_COLS]; MAP_ROWS];
+    for (x, y) in tiles() {
+        let fire = danger[y][x].map_or(u32::MAX, |f| f.from);
+        cover[y][x] = steps[y][x].is_some_and(|n| n * pace + SAFETY_TICKS < fire);
+    }
+    cover
+}
+
+fn manhattan(a: TilePos, b: TilePos) -> u32 {
+    (a.0.abs_diff(b.0) + a.1.abs_diff(b.1)) as u32
+}
+
+/// Where to run after dropping a bomb at `here`, if anywhere is reachable in
+/// time.
+fn escape_after_bomb(
+    world: &World,
+    me: &PlayerView,
+    here: TilePos,
+    skill: &Profile,
+) -> Option<TilePos> {
+    let bomb = BombView {
+        x: here.0,
+        y: here.1,
+        timer: BOMB_TIMER_TICKS,
+        range: me.bomb_range,
+    };
+    let danger = fire_map(world, &[bomb]);
+    let paths = search(world.map, me, &danger, skill);
+    nearest(&paths, |pos| danger[pos.1][pos.0].is_none())
+}
+
+/// Direction toward the centre of `goal`, straightening up first when too far
+/// off the lane to turn. `None` once the bot is there.
+fn steer(x: i32, y: i32, goal: TilePos, speed: i32) -> Option<(i8, i8)> {
+    let ex = goal.0 as i32 * SUBTILE - x;
+    let ey = goal.1 as i32 * SUBTILE - y;
+    let close = speed / 2 + 1;
+    if ex.abs() < close && ey.abs() < close {
+        return None;
+    }
+    // A fast bot can't get closer than half a step, so don't try.
+    let align = ALIGN.max(close);
+    let dir = |e: i32| e.signum() as i8;
+    if ex.abs() >= ey.abs() {
+        if ey.abs() > align {
+            Some((0, dir(ey)))
+        } else {
+            Some((dir(ex), 0))
+        }
+    } else if ex.abs() > align {
+        Some((dir(ex), 0))
+    } else {
+        Some((0, dir(ey)))
+    }
+}
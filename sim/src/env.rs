@@ -0,0 +1,307 @@
+//! A step-at-a-time environment for training agents offline, running the
+//! same `GameInstance` and rules as the live server.
+//!
+//! Call `reset` with a seed, then `step` with one action per seat until it
+//! reports the episode done.
+
+use serde::{Deserialize, Serialize};
+use shared::constants::*;
+use shared::map::Tile;
+use shared::physics;
+use shared::protocol::{BotAction, BotDifficulty, ItemKind};
+use shared::rules::Rules;
+
+use crate::game::GameInstance;
+
+/// Channels of `Observation::grid`, each a `MAP_ROWS` x `MAP_COLS` plane.
+pub mod channel {
+    use shared::constants::MAX_PLAYERS;
+
+    pub const HARD_BLOCK: usize = 0;
+    pub const SOFT_BLOCK: usize = 1;
+    /// Fraction of the fuse left on a bomb, in `(0, 1]`.
+    pub const BOMB_TIMER: usize = 2;
+    /// Blast range of a bomb, in tiles.
+    pub const BOMB_RANGE: usize = 3;
+    pub const FLAME: usize = 4;
+    pub const ITEM_BOMB_RANGE: usize = 5;
+    pub const ITEM_BOMB_COUNT: usize = 6;
+    pub const ITEM_SPEED: usize = 7;
+    /// The tile of seat `i`, while alive, is set in channel `PLAYER + i`.
+    pub const PLAYER: usize = 8;
+    pub const COUNT: usize = PLAYER + MAX_PLAYERS;
+}
+
+/// Who plays a seat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Controller {
+    /// Driven by the actions passed to `Env::step`.
+    Agent,
+    /// Driven by the built-in bot; its entry in the actions is ignored.
+    Bot(BotDifficulty),
+}
+
+/// Reward for each event, per seat. Everything but the final outcome is
+/// shaping and defaults to zero or close to it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rewards {
+    pub win: f32,
+    /// Given on the tick the seat is caught in a blast.
+    pub death: f32,
+    /// Given to every seat still alive when nobody wins or time runs out.
+    pub draw: f32,
+    /// Given every tick the seat is alive.
+    pub alive: f32,
+    pub item: f32,
+    /// Given to every other living seat when a seat is eliminated.
+    pub opponent_out: f32,
+}
+
+impl Default for Rewards {
+    fn default() -> Self {
+        Rewards {
+            win: 1.0,
+            death: -1.0,
+            draw: 0.0,
+            alive: 0.0,
+            item: 0.1,
+            opponent_out: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvConfig {
+    pub rules: Rules,
+    /// One entry per seat, between `MIN_PLAYERS` and `MAX_PLAYERS`.
+    pub seats: Vec<Controller>,
+    /// An episode is cut off, as a draw, after this many ticks.
+    pub max_ticks: u64,
+    pub rewards: Rewards,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            rules: Rules::default(),
+            seats: vec![
+                Controller::Agent,
+                Controller::Bot(BotDifficulty::Medium),
+                Controller::Bot(BotDifficulty::Medium),
+                Controller::Bot(BotDifficulty::Medium),
+            ],
+            max_ticks: 6000,
+            rewards: Rewards::default(),
+        }
+    }
+}
+
+/// Per-seat features that do not fit on the grid. Seats beyond the
+/// configured count are left at their defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SeatFeatures {
+    pub alive: bool,
+    /// Position in tiles, with fractions while between tiles.
+    pub x: f32,
+    pub y: f32,
+    pub bombs_left: u32,
+    pub bomb_range: u32,
+    /// `SUBTILE` units per tick.
+    pub speed: i32,
+    pub invulnerable: bool,
+}
+
+/// The whole game as fixed-shape arrays, identical for every seat.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Observation {
+    pub tick: u64,
+    /// `channel::COUNT * MAP_ROWS * MAP_COLS` values, indexed by `index`.
+    pub grid: Vec<f32>,
+    pub seats: [SeatFeatures; MAX_PLAYERS],
+}
+
+impl Observation {
+    /// Position of a cell of `grid`.
+    pub fn index(channel: usize, x: usize, y: usize) -> usize {
+        (channel * MAP_ROWS + y) * MAP_COLS + x
+    }
+
+    pub fn get(&self, channel: usize, x: usize, y: usize) -> f32 {
+        self.grid[Self::index(channel, x, y)]
+    }
+}
+
+/// A game stepped one tick at a time. Clone it to snapshot the state, for
+/// example to branch in a tree search; clones step identically.
+#[derive(Clone)]
+pub struct Env {
+    pub config: EnvConfig,
+    game: GameInstance,
+}
+
+impl Env {
+    pub fn new(config: EnvConfig) -> Self {
+        assert!(
+            (MIN_PLAYERS..=MAX_PLAYERS).contains(&config.seats.len()),
+            "an environment needs {} to {} seats",
+            MIN_PLAYERS,
+            MAX_PLAYERS
+        );
+        let game = GameInstance::new(config.rules, 0);
+        Env { config, game }
+    }
+
+    /// Starts a new episode. The same seed and actions give the same episode.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.game = GameInstance::new(self.config.rules, seed);
+        for (seat, controller) in self.config.seats.iter().enumerate() {
+            let id = seat as u8;
+            match controller {
+                Controller::Agent => self.game.add_player(id, format!("Agent {}", seat)),
+                Controller::Bot(difficulty) => {
+                    let name = format!("{:?} Bot {}", difficulty, seat);
+                    self.game.add_bot(id, name, *difficulty);
+                }
+            }
+        }
+        self.game.start();
+        self.observe()
+    }
+
+    /// Applies one action per seat for one tick and returns the new
+    /// observation, each seat's reward and whether the episode is over.
+    /// Missing actions count as `Stay`.
+    pub fn step(&mut self, actions: &[BotAction]) -> (Observation, Vec<f32>, bool) {
+        let rewards = self.config.rewards;
+        let seats = self.config.seats.len();
+        if self.is_done() {
+            return (self.observe(), vec![0.0; seats], true);
+        }
+
+        let before: Vec<(bool, u32)> = (0..seats).map(|seat| self.status(seat)).collect();
+        for (seat, controller) in self.config.seats.iter().enumerate() {
+            if *controller != Controller::Agent {
+                continue;
+            }
+            let action = actions.get(seat).copied().unwrap_or(BotAction::Stay);
+            let (dx, dy) = action.direction();
+            let id = seat as u8;
+            self.game
+                .queue_player_input(id, self.game.tick as u32 + 1, dx, dy);
+            if action == BotAction::Bomb {
+                self.game.queue_bomb(id);
+            }
+        }
+        self.game.tick();
+
+        let after: Vec<(bool, u32)> = (0..seats).map(|seat| self.status(seat)).collect();
+        let eliminated = (0..seats)
+            .filter(|&seat| before[seat].0 && !after[seat].0)
+            .count() as f32;
+        let done = self.is_done();
+        let mut reward = vec![0.0; seats];
+        for seat in 0..seats {
+            let ((was_alive, items_before), (alive, items_after)) = (before[seat], after[seat]);
+            if !was_alive {
+                continue;
+            }
+            let r = &mut reward[seat];
+            *r += rewards.item * items_after.saturating_sub(items_before) as f32;
+            if alive {
+                *r += rewards.alive + rewards.opponent_out * eliminated;
+            } else {
+                *r += rewards.death + rewards.opponent_out * (eliminated - 1.0);
+            }
+            if done && alive {
+                *r += if self.game.winner == Some(seat as u8) {
+                    rewards.win
+                } else {
+                    rewards.draw
+                };
+            }
+        }
+        (self.observe(), reward, done)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.game.finished || self.game.tick >= self.config.max_ticks
+    }
+
+    pub fn game(&self) -> &GameInstance {
+        &self.game
+    }
+
+    // Whether a seat is alive and how many items it has picked up.
+    fn status(&self, seat: usize) -> (bool, u32) {
+        let Some(p) = self.game.players.iter().find(|p| p.id == seat as u8) else {
+            return (false, 0);
+        };
+        let items = (p.bomb_range - DEFAULT_BOMB_RANGE)
+            + (p.max_bombs - DEFAULT_MAX_BOMBS)
+            + ((p.speed - DEFAULT_SPEED) / SPEED_BOOST) as u32;
+        (p.alive, items)
+    }
+
+    pub fn observe(&self) -> Observation {
+        let game = &self.game;
+        let mut grid = vec![0.0; channel::COUNT * MAP_ROWS * MAP_COLS];
+        let mut set = |channel: usize, x: usize, y: usize, value: f32| {
+            grid[Observation::index(channel, x, y)] = value;
+        };
+
+        for (y, row) in game.map.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                match tile {
+                    Tile::HardBlock => set(channel::HARD_BLOCK, x, y, 1.0),
+                    Tile::SoftBlock => set(channel::SOFT_BLOCK, x, y, 1.0),
+                    Tile::Empty => {}
+                }
+            }
+        }
+        for bomb in &game.bombs {
+            let fuse = bomb.timer as f32 / BOMB_TIMER_TICKS as f32;
+            set(channel::BOMB_TIMER, bomb.x, bomb.y, fuse);
+            set(channel::BOMB_RANGE, bomb.x, bomb.y, bomb.range as f32);
+        }
+        for exp in &game.explosions {
+            set(channel::FLAME, exp.x, exp.y, 1.0);
+        }
+        for item in &game.items {
+            let channel = match item.kind {
+                ItemKind::BombRange => channel::ITEM_BOMB_RANGE,
+                ItemKind::BombCount => channel::ITEM_BOMB_COUNT,
+                ItemKind::Speed => channel::ITEM_SPEED,
+            };
+            set(channel, item.x, item.y, 1.0);
+        }
+
+        let mut seats = [SeatFeatures::default(); MAX_PLAYERS];
+        for p in &game.players {
+            let seat = p.id as usize;
+            if seat >= MAX_PLAYERS {
+                continue;
+            }
+            if p.alive {
+                let (x, y) = (physics::tile_of(p.x), physics::tile_of(p.y));
+                set(channel::PLAYER + seat, x, y, 1.0);
+            }
+            seats[seat] = SeatFeatures {
+                alive: p.alive,
+                x: physics::to_tiles(p.x) as f32,
+                y: physics::to_tiles(p.y) as f32,
+                bombs_left: p.max_bombs.saturating_sub(p.active_bombs),
+                bomb_range: p.bomb_range,
+                speed: p.speed,
+                invulnerable: p.invulnerable_ticks > 0,
+            };
+        }
+
+        Observation {
+            tick: game.tick,
+            grid,
+            seats,
+        }
+    }
+}
@@ -0,0 +1,832 @@
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use shared::constants::*;
+use shared::map::{self, GameMap, Tile};
+use shared::physics;
+use shared::protocol::*;
+use shared::rules::Rules;
+
+use crate::bot::{self, BombView, Bot, PlayerView, World};
+
+// Inputs beyond this many are dropped oldest-first so a client that runs
+// ahead of the server cannot build up unbounded input latency.
+const MAX_QUEUED_INPUTS: usize = 8;
+
+#[derive(Clone)]
+struct MoveInput {
+    seq: u32,
+    dx: i8,
+    dy: i8,
+}
+
+/// One player's input as applied on a tick: the direction held that tick,
+/// the sequence number it acknowledges, whether a bomb was placed and
+/// whether the player left the game.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub player_id: u8,
+    pub seq: u32,
+    pub dx: i8,
+    pub dy: i8,
+    pub bomb: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub forfeit: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// Every input applied on one tick, in the order it was applied. Feeding the
+/// same records back through `GameInstance::step` replays the game.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TickInputs {
+    pub tick: u64,
+    pub inputs: Vec<PlayerInput>,
+}
+
+#[derive(Clone)]
+pub struct Player {
+    pub id: u8,
+    pub name: String,
+    /// Position in `SUBTILE` units.
+    pub x: i32,
+    pub y: i32,
+    pub alive: bool,
+    pub speed: i32,
+    pub bomb_range: u32,
+    pub max_bombs: u32,
+    pub active_bombs: u32,
+    pub color_index: u8,
+    pub dx: i8,
+    pub dy: i8,
+    pub last_seq: u32,
+    pub invulnerable_ticks: u32,
+    inputs: VecDeque<MoveInput>,
+    // Set by any number of bomb presses since the last tick.
+    bomb_pressed: bool,
+    forfeit: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct Bomb {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    owner: u8,
+    pub(crate) timer: u32,
+    pub(crate) range: u32,
+}
+
+#[derive(Clone)]
+pub(crate) struct Explosion {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    timer: u32,
+    // Tick of the blast, so it only burns items that were there before it.
+    started: u64,
+}
+
+#[derive(Clone)]
+pub(crate) struct Item {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) kind: ItemKind,
+    dropped: u64,
+}
+
+/// How many of each item kind, for balance statistics.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemCounts {
+    pub bomb_range: u32,
+    pub bomb_count: u32,
+    pub speed: u32,
+}
+
+impl ItemCounts {
+    pub fn add(&mut self, kind: ItemKind) {
+        match kind {
+            ItemKind::BombRange => self.bomb_range += 1,
+            ItemKind::BombCount => self.bomb_count += 1,
+            ItemKind::Speed => self.speed += 1,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.bomb_range + self.bomb_count + self.speed
+    }
+}
+
+/// What happened to the items of one game.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStats {
+    pub dropped: ItemCounts,
+    pub picked: ItemCounts,
+    pub burned: ItemCounts,
+}
+
+#[derive(Clone)]
+pub struct GameInstance {
+    pub rules: Rules,
+    /// Seeds item drops and bots; the same seed and inputs replay the game.
+    pub seed: u64,
+    rng: StdRng,
+    pub map: GameMap,
+    pub players: Vec<Player>,
+    pub(crate) bombs: Vec<Bomb>,
+    pub(crate) explosions: Vec<Explosion>,
+    pub(crate) items: Vec<Item>,
+    bots: Vec<Bot>,
+    pub tick: u64,
+    pub running: bool,
+    pub finished: bool,
+    pub winner: Option<u8>,
+    pub item_stats: ItemStats,
+}
+
+impl GameInstance {
+    pub fn new(rules: Rules, seed: u64) -> Self {
+        GameInstance {
+            rules,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            map: map::generate_map(),
+            players: Vec::new(),
+            bombs: Vec::new(),
+            explosions: Vec::new(),
+            items: Vec::new(),
+            bots: Vec::new(),
+            tick: 0,
+            running: false,
+            finished: false,
+            winner: None,
+            item_stats: ItemStats::default(),
+        }
+    }
+
+    pub fn add_player(&mut self, id: u8, name: String) {
+        let spawns = map::spawn_positions();
+        // Take the first free spawn, since players can leave before the start.
+        let idx = (0..spawns.len())
+            .find(|i| self.players.iter().all(|p| p.color_index as usize != *i))
+            .unwrap_or(spawns.len() - 1);
+        self.add_player_at(id, name, idx);
+    }
+
+    /// Adds a player at a given spawn, which is also its colour.
+    pub fn add_player_at(&mut self, id: u8, name: String, idx: usize) {
+        let (sr, sc) = map::spawn_positions()[idx];
+        self.players.push(Player {
+            id,
+            name,
+            x: sc as i32 * SUBTILE,
+            y: sr as i32 * SUBTILE,
+            alive: true,
+            speed: DEFAULT_SPEED,
+            bomb_range: DEFAULT_BOMB_RANGE,
+            max_bombs: DEFAULT_MAX_BOMBS,
+            active_bombs: 0,
+            color_index: idx as u8,
+            dx: 0,
+            dy: 0,
+            last_seq: 0,
+            invulnerable_ticks: 0,
+            inputs: VecDeque::new(),
+            bomb_pressed: false,
+            forfeit: false,
+        });
+    }
+
+    /// Removes a player that left before the game started.
+    pub fn remove_player(&mut self, player_id: u8) {
+        self.players.retain(|p| p.id != player_id);
+        self.bots.retain(|b| b.player_id != player_id);
+    }
+
+    /// Adds a player driven by a bot instead of a client.
+    pub fn add_bot(&mut self, id: u8, name: String, difficulty: BotDifficulty) {
+        self.add_player(id, name);
+        self.set_bot(id, Some(difficulty));
+    }
+
+    /// Hands a player over to a bot, or back to its client with `None`.
+    pub fn set_bot(&mut self, player_id: u8, difficulty: Option<BotDifficulty>) {
+        self.bots.retain(|b| b.player_id != player_id);
+        if let Some(difficulty) = difficulty {
+            let seed = self.seed ^ ((player_id as u64) << 56);
+            self.bots.push(Bot::new(player_id, difficulty, seed));
+        }
+        self.set_player_idle(player_id);
+    }
+
+    pub fn is_bot(&self, player_id: u8) -> bool {
+        self.bot_difficulty(player_id).is_some()
+    }
+
+    pub fn bot_difficulty(&self, player_id: u8) -> Option<BotDifficulty> {
+        self.bots
+            .iter()
+            .find(|b| b.player_id == player_id)
+            .map(|b| b.difficulty)
+    }
+
+    /// Takes a player who left out of the game on the next tick.
+    pub fn forfeit(&mut self, player_id: u8) {
+        if let Some(p) = self.players.iter_mut().find(|p| p.id == player_id) {
+            p.forfeit = true;
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.running = true;
+        for p in self.players.iter_mut() {
+            p.invulnerable_ticks = SPAWN_INVULNERABILITY_TICKS;
+        }
+    }
+
+    /// Queues one tick of sequenced movement input. Queued inputs are applied
+    /// one per tick; when the queue runs dry the last direction is held.
+    pub fn queue_player_input(&mut self, player_id: u8, seq: u32, dx: i8, dy: i8) {
+        if let Some(p) = self.players.iter_mut().find(|p| p.id == player_id) {
+            if seq <= p.last_seq {
+                return;
+            }
+            if p.inputs.len() >= MAX_QUEUED_INPUTS {
+                p.inputs.pop_front();
+            }
+            p.inputs.push_back(MoveInput {
+                seq,
+                dx: dx.signum(),
+                dy: dy.signum(),
+            });
+        }
+    }
+
+    /// Stops a player in place and drops any queued input.
+    pub fn set_player_idle(&mut self, player_id: u8) {
+        if let Some(p) = self.players.iter_mut().find(|p| p.id == player_id) {
+            p.dx = 0;
+            p.dy = 0;
+            p.inputs.clear();
+            p.bomb_pressed = false;
+        }
+    }
+
+    /// Queues a bomb press for the next tick. Presses in the same tick merge.
+    pub fn queue_bomb(&mut self, player_id: u8) {
+        if let Some(p) = self.players.iter_mut().find(|p| p.id == player_id) {
+            p.bomb_pressed = true;
+        }
+    }
+
+    fn place_bomb(&mut self, player_id: u8) {
+        let (px, py, range, can_place) = {
+            if let Some(p) = self.players.iter().find(|p| p.id == player_id && p.alive) {
+                let bx = physics::tile_of(p.x);
+                let by = physics::tile_of(p.y);
+                (bx, by, p.bomb_range, p.active_bombs < p.max_bombs)
+            } else {
+                return;
+            }
+        };
+
+        if !can_place {
+            return;
+        }
+
+        let already_has_bomb = self.bombs.iter().any(|b| b.x == px && b.y == py);
+        if already_has_bomb {
+            return;
+        }
+
+        self.bombs.push(Bomb {
+            x: px,
+            y: py,
+            owner: player_id,
+            timer: BOMB_TIMER_TICKS,
+            range,
+        });
+
+        if let Some(p) = self.players.iter_mut().find(|p| p.id == player_id) {
+            p.active_bombs += 1;
+        }
+    }
+
+    /// Advances one tick using the inputs players queued since the last one,
+    /// and returns the record of what was applied.
+    pub fn tick(&mut self) -> Option<TickInputs> {
+        if !self.running || self.finished {
+            return None;
+        }
+        let inputs = self.take_inputs();
+        self.step(&inputs);
+        Some(inputs)
+    }
+
+    /// Drains each living player's queues into this tick's inputs, in player
+    /// id order. A player with no queued movement keeps its last direction.
+    fn take_inputs(&mut self) -> TickInputs {
+        self.run_bots();
+        let mut inputs = Vec::new();
+        for p in self.players.iter_mut() {
+            if !p.alive {
+                continue;
+            }
+            if let Some(input) = p.inputs.pop_front() {
+                p.dx = input.dx;
+                p.dy = input.dy;
+                p.last_seq = input.seq;
+            }
+            inputs.push(PlayerInput {
+                player_id: p.id,
+                seq: p.last_seq,
+                dx: p.dx,
+                dy: p.dy,
+                bomb: std::mem::take(&mut p.bomb_pressed),
+                forfeit: std::mem::take(&mut p.forfeit),
+            });
+        }
+        inputs.sort_by_key(|input| input.player_id);
+        TickInputs {
+            tick: self.tick + 1,
+            inputs,
+        }
+    }
+
+    // Lets each bot queue its input for this tick, as a client would.
+    fn run_bots(&mut self) {
+        if self.bots.is_empty() {
+            return;
+        }
+        let mut bots = std::mem::take(&mut self.bots);
+        let world = self.world();
+        let decisions: Vec<(u8, i8, i8, bool)> = bots
+            .iter_mut()
+            .map(|bot| {
+                let (dx, dy, bomb) = bot.next_input(&world);
+                (bot.player_id, dx, dy, bomb)
+            })
+            .collect();
+        self.bots = bots;
+
+        for (player_id, dx, dy, bomb) in decisions {
+            if let Some(p) = self.players.iter_mut().find(|p| p.id == player_id) {
+                p.dx = dx;
+                p.dy = dy;
+                p.bomb_pressed |= bomb;
+            }
+        }
+    }
+
+    // The game as bots see it.
+    fn world(&self) -> World<'_> {
+        World {
+            map: &self.map,
+            bombs: self
+                .bombs
+                .iter()
+                .map(|b| BombView {
+                    x: b.x,
+                    y: b.y,
+                    timer: b.timer,
+                    range: b.range,
+                })
+                .collect(),
+            burning: self
+                .explosions
+                .iter()
+                .map(|e| ((e.x, e.y), e.timer))
+                .collect(),
+            items: self.items.iter().map(|i| (i.x, i.y)).collect(),
+            players: self
+                .players
+                .iter()
+                .map(|p| PlayerView {
+                    id: p.id,
+                    x: p.x,
+                    y: p.y,
+                    alive: p.alive,
+                    speed: p.speed,
+                    bomb_range: p.bomb_range,
+                    can_bomb: p.active_bombs < p.max_bombs,
+                })
+                .collect(),
+        }
+    }
+
+    /// What an external bot controlling `player_id` sees this tick.
+    pub fn observation(&self, player_id: u8) -> Option<Observation> {
+        let me = self.players.iter().find(|p| p.id == player_id)?;
+        let (mx, my) = (physics::tile_of(me.x), physics::tile_of(me.y));
+        let rel = |x: usize, y: usize| (x as i32 - mx as i32, y as i32 - my as i32);
+
+        let grid = self
+            .map
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|tile| match tile {
+                        Tile::Empty => '.',
+                        Tile::HardBlock => '#',
+                        Tile::SoftBlock => '+',
+                    })
+                    .collect()
+            })
+            .collect();
+        let danger = bot::danger_map(&self.world(), None)
+            .iter()
+            .map(|row| row.to_vec())
+            .collect();
+        let players = self
+            .players
+            .iter()
+            .filter(|p| p.id != player_id)
+            .map(|p| {
+                let (dx, dy) = rel(physics::tile_of(p.x), physics::tile_of(p.y));
+                BotEntity {
+                    id: p.id,
+                    dx,
+                    dy,
+                    alive: p.alive,
+                }
+            })
+            .collect();
+        let bombs = self
+            .bombs
+            .iter()
+            .map(|b| {
+                let (dx, dy) = rel(b.x, b.y);
+                BotBomb {
+                    dx,
+                    dy,
+                    timer: b.timer,
+                    range: b.range,
+                }
+            })
+            .collect();
+        let items = self
+            .items
+            .iter()
+            .map(|i| {
+                let (dx, dy) = rel(i.x, i.y);
+                BotItem {
+                    dx,
+                    dy,
+                    kind: i.kind,
+                }
+            })
+            .collect();
+
+        Some(Observation {
+            tick: self.tick,
+            grid,
+            danger,
+            me: BotSelf {
+                id: me.id,
+                x: mx,
+                y: my,
+                offset_x: me.x - mx as i32 * SUBTILE,
+                offset_y: me.y - my as i32 * SUBTILE,
+                alive: me.alive,
+                bombs_left: me.max_bombs.saturating_sub(me.active_bombs),
+                bomb_range: me.bomb_range,
+                speed: me.speed,
+            },
+            players,
+            bombs,
+            items,
+        })
+    }
+
+    /// Advances one tick with exactly `inputs` applied, ignoring anything
+    /// queued. Used by `tick` and to replay recorded inputs.
+    pub fn step(&mut self, inputs: &TickInputs) {
+        if !self.running || self.finished {
+            return;
+        }
+        self.tick += 1;
+        for input in &inputs.inputs {
+            if let Some(p) = self.players.iter_mut().find(|p| p.id == input.player_id) {
+                p.dx = input.dx;
+                p.dy = input.dy;
+                p.last_seq = input.seq;
+                if input.forfeit {
+                    p.alive = false;
+                    continue;
+                }
+            }
+            if input.bomb {
+                self.place_bomb(input.player_id);
+            }
+        }
+        self.move_players();
+        self.update_bombs();
+        self.update_explosions();
+        self.check_item_pickup();
+        self.check_game_over();
+    }
+
+    fn move_players(&mut self) {
+        for p in self.players.iter_mut() {
+            if !p.alive {
+                continue;
+            }
+            p.invulnerable_ticks = p.invulnerable_ticks.saturating_sub(1);
+            (p.x, p.y) = physics::move_player(
+                &self.map,
+                p.x,
+                p.y,
+                p.dx,
+                p.dy,
+                p.speed,
+                self.rules.corner_tolerance,
+            );
+        }
+    }
+
+    fn update_bombs(&mut self) {
+        for bomb in self.bombs.iter_mut() {
+            if bomb.timer > 0 {
+                bomb.timer -= 1;
+            }
+        }
+
+        // Explode every bomb that's due, including any a blast sets off on
+        // the way, and hand each one back to its owner.
+        while let Some(i) = self.bombs.iter().position(|b| b.timer == 0) {
+            let bomb = self.bombs.remove(i);
+            self.create_explosion(bomb.x, bomb.y, bomb.range);
+            if let Some(p) = self.players.iter_mut().find(|p| p.id == bomb.owner) {
+                if p.active_bombs > 0 {
+                    p.active_bombs -= 1;
+                }
+            }
+        }
+    }
+
+    fn create_explosion(&mut self, cx: usize, cy: usize, range: u32) {
+        self.explosions.push(Explosion {
+            x: cx,
+            y: cy,
+            timer: EXPLOSION_DURATION_TICKS,
+            started: self.tick,
+        });
+
+        let directions: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+        for (ddx, ddy) in &directions {
+            for i in 1..=(range as i32) {
+                let nx = cx as i32 + ddx * i;
+                let ny = cy as i32 + ddy * i;
+                if nx < 0 || ny < 0 || nx >= MAP_COLS as i32 || ny >= MAP_ROWS as i32 {
+                    break;
+                }
+                let ux = nx as usize;
+                let uy = ny as usize;
+
+                match self.map[uy][ux] {
+                    Tile::HardBlock => break,
+                    Tile::SoftBlock => {
+                        self.map[uy][ux] = Tile::Empty;
+                        self.explosions.push(Explosion {
+                            x: ux,
+                            y: uy,
+                            timer: EXPLOSION_DURATION_TICKS,
+                            started: self.tick,
+                        });
+                        if self.rng.gen_ratio(1, 3) {
+                            let kind = match self.rng.gen_range(0..3) {
+                                0 => ItemKind::BombRange,
+                                1 => ItemKind::BombCount,
+                                _ => ItemKind::Speed,
+                            };
+                            self.item_stats.dropped.add(kind);
+                            self.items.push(Item {
+                                x: ux,
+                                y: uy,
+                                kind,
+                                dropped: self.tick,
+                            });
+                        }
+                        break;
+                    }
+                    Tile::Empty => {
+                        self.explosions.push(Explosion {
+                            x: ux,
+                            y: uy,
+                            timer: EXPLOSION_DURATION_TICKS,
+                            started: self.tick,
+                        });
+                    }
+                }
+            }
+        }
+
+        // Kill players in explosions
+        for p in self.players.iter_mut() {
+            if !p.alive || p.invulnerable_ticks > 0 {
+                continue;
+            }
+            let px = physics::tile_of(p.x);
+            let py = physics::tile_of(p.y);
+            for exp in &self.explosions {
+                if exp.x == px && exp.y == py {
+                    p.alive = false;
+                    break;
+                }
+            }
+        }
+
+        // Chain explosions: detonate any bomb caught in the blast
+        let mut chain: Vec<usize> = Vec::new();
+        for (i, bomb) in self.bombs.iter().enumerate() {
+            for exp in &self.explosions {
+                if bomb.x == exp.x && bomb.y == exp.y && bomb.timer > 0 {
+                    chain.push(i);
+                    break;
+                }
+            }
+        }
+        for i in chain.into_iter().rev() {
+            self.bombs[i].timer = 0;
+        }
+    }
+
+    fn update_explosions(&mut self) {
+        for exp in self.explosions.iter_mut() {
+            if exp.timer > 0 {
+                exp.timer -= 1;
+            }
+        }
+        // Remove items caught in explosions, except the one that uncovered
+        // them: an item burns in a later blast.
+        let explosions = &self.explosions;
+        let burned = &mut self.item_stats.burned;
+        self.items.retain(|item| {
+            let caught = explosions
+                .iter()
+                .any(|e| e.x == item.x && e.y == item.y && e.timer > 0 && e.started > item.dropped);
+            if caught {
+                burned.add(item.kind);
+            }
+            !caught
+        });
+        self.explosions.retain(|e| e.timer > 0);
+    }
+
+    fn check_item_pickup(&mut self) {
+        let mut picked = Vec::new();
+        for (i, item) in self.items.iter().enumerate() {
+            for p in self.players.iter_mut() {
+                if !p.alive {
+                    continue;
+                }
+                let px = physics::tile_of(p.x);
+                let py = physics::tile_of(p.y);
+                if px == item.x && py == item.y {
+                    match item.kind {
+                        ItemKind::BombRange => p.bomb_range += 1,
+                        ItemKind::BombCount => p.max_bombs += 1,
+                        ItemKind::Speed => p.speed += SPEED_BOOST,
+                    }
+                    self.item_stats.picked.add(item.kind);
+                    picked.push(i);
+                    break;
+                }
+            }
+        }
+        for i in picked.into_iter().rev() {
+            self.items.remove(i);
+        }
+    }
+
+    fn check_game_over(&mut self) {
+        let alive: Vec<u8> = self
+            .players
+            .iter()
+            .filter(|p| p.alive)
+            .map(|p| p.id)
+            .collect();
+
+        if alive.len() <= 1 && self.players.len() >= 2 {
+            self.finished = true;
+            self.running = false;
+            self.winner = alive.first().copied();
+        }
+    }
+
+    pub fn get_state_msg(&self) -> ServerMsg {
+        ServerMsg::GameState {
+            players: self.player_states(),
+            bombs: self
+                .bombs
+                .iter()
+                .map(|b| BombState {
+                    x: b.x,
+                    y: b.y,
+                    owner: b.owner,
+                    timer: b.timer,
+                })
+                .collect(),
+            explosions: self
+                .explosions
+                .iter()
+                .map(|e| ExplosionState {
+                    x: e.x,
+                    y: e.y,
+                    timer: e.timer,
+                })
+                .collect(),
+            items: self
+                .items
+                .iter()
+                .map(|i| ItemState {
+                    x: i.x,
+                    y: i.y,
+                    kind: i.kind,
+                })
+                .collect(),
+            map: self.map.iter().map(|row| row.to_vec()).collect(),
+            tick: self.tick,
+        }
+    }
+
+    /// FNV-1a hash of everything clients are sent, to check that a replay
+    /// ends exactly where the recorded game did.
+    pub fn state_hash(&self) -> u64 {
+        let state = serde_json::to_vec(&self.get_state_msg()).unwrap();
+        state.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+
+    /// The simulation state as text, one entity per line, for diffing two
+    /// games that should be identical.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "tick {} running={} finished={} winner={:?}",
+            self.tick, self.running, self.finished, self.winner
+        )];
+        for p in &self.players {
+            lines.push(format!(
+                "player {} x={} y={} alive={} speed={} range={} bombs={}/{} dir=({},{}) seq={} invulnerable={}",
+                p.id,
+                p.x,
+                p.y,
+                p.alive,
+                p.speed,
+                p.bomb_range,
+                p.active_bombs,
+                p.max_bombs,
+                p.dx,
+                p.dy,
+                p.last_seq,
+                p.invulnerable_ticks
+            ));
+        }
+        for b in &self.bombs {
+            lines.push(format!(
+                "bomb ({},{}) owner={} timer={} range={}",
+                b.x, b.y, b.owner, b.timer, b.range
+            ));
+        }
+        for e in &self.explosions {
+            lines.push(format!("flame ({},{}) timer={}", e.x, e.y, e.timer));
+        }
+        for i in &self.items {
+            lines.push(format!("item ({},{}) {:?}", i.x, i.y, i.kind));
+        }
+        for (y, row) in self.map.iter().enumerate() {
+            let row: String = row
+                .iter()
+                .map(|tile| match tile {
+                    Tile::Empty => '.',
+                    Tile::HardBlock => '#',
+                    Tile::SoftBlock => '+',
+                })
+                .collect();
+            lines.push(format!("row {:2} {}", y, row));
+        }
+        lines
+    }
+
+    pub fn player_states(&self) -> Vec<PlayerState> {
+        self.players
+            .iter()
+            .map(|p| PlayerState {
+                id: p.id,
+                name: p.name.clone(),
+                x: p.x,
+                y: p.y,
+                alive: p.alive,
+                speed: p.speed,
+                bomb_range: p.bomb_range,
+                max_bombs: p.max_bombs,
+                color_index: p.color_index,
+                invulnerable: p.invulnerable_ticks > 0,
+                last_seq: p.last_seq,
+            })
+            .collect()
+    }
+}
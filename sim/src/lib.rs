@@ -0,0 +1,7 @@
+//! The game simulation, independent of networking, so it can run inside a
+//! room task or as fast as possible in batch.
+
+pub mod bot;
+pub mod env;
+pub mod game;
+pub mod replay;
@@ -0,0 +1,347 @@
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+
+use shared::constants::{MAX_PLAYERS, MIN_PLAYERS, TICK_RATE_MS};
+use shared::protocol::BotDifficulty;
+use shared::rules::Rules;
+use sim::game::{GameInstance, ItemCounts, ItemStats};
+use sim::replay::Replay;
+
+const USAGE: &str = "\
+Runs bot-only matches as fast as possible and prints balance statistics.
+
+Usage: sim [options]
+       sim verify [--bless] REPLAY...
+
+Options:
+  --games N        matches to play (default 1000)
+  --seed N         seed of the first match; match i uses seed + i (default 0)
+  --rules FILE     TOML rules file, e.g. `corner_tolerance = 64`
+  --bots LIST      comma-separated difficulty per seat (default hard,hard,hard,hard)
+  --max-ticks N    give up on a match after this many ticks (default 6000)
+  --threads N      worker threads (default: all cores)
+  --record DIR     save every match as a replay in DIR
+
+`verify` re-simulates replays and reports the first tick that differs from
+the recording. `--bless` rewrites the recorded results instead, for a
+gameplay change that is meant to alter them.";
+
+struct Options {
+    games: u64,
+    seed: u64,
+    rules: Rules,
+    bots: Vec<BotDifficulty>,
+    max_ticks: u64,
+    threads: usize,
+    record: Option<PathBuf>,
+}
+
+/// How one match ended.
+struct MatchResult {
+    winner: Option<u8>,
+    ticks: u64,
+    timed_out: bool,
+    items: ItemStats,
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("verify") {
+        args.next();
+        process::exit(verify(args.collect()));
+    }
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("sim: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let results = run_all(&options);
+    report(&options, &results);
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        games: 1000,
+        seed: 0,
+        rules: Rules::default(),
+        bots: vec![BotDifficulty::Hard; MAX_PLAYERS],
+        max_ticks: 6000,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        record: None,
+    };
+
+    while let Some(flag) = args.next() {
+        if flag == "-h" || flag == "--help" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--games" => options.games = parse_number(&flag, &value)?,
+            "--seed" => options.seed = parse_number(&flag, &value)?,
+            "--rules" => options.rules = load_rules(Path::new(&value))?,
+            "--bots" => options.bots = parse_bots(&value)?,
+            "--max-ticks" => options.max_ticks = parse_number(&flag, &value)?,
+            "--threads" => options.threads = parse_number::<usize>(&flag, &value)?.max(1),
+            "--record" => options.record = Some(PathBuf::from(value)),
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got {:?}", flag, value))
+}
+
+fn load_rules(path: &Path) -> Result<Rules, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let rules: Rules =
+        toml::from_str(&text).map_err(|e| format!("invalid rules in {}: {}", path.display(), e))?;
+    rules
+        .validate()
+        .map_err(|e| format!("invalid rules in {}: {}", path.display(), e))?;
+    Ok(rules)
+}
+
+fn parse_bots(list: &str) -> Result<Vec<BotDifficulty>, String> {
+    let bots = list
+        .split(',')
+        .map(|name| match name.trim().to_ascii_lowercase().as_str() {
+            "easy" => Ok(BotDifficulty::Easy),
+            "medium" => Ok(BotDifficulty::Medium),
+            "hard" => Ok(BotDifficulty::Hard),
+            other => Err(format!("unknown difficulty {:?}", other)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if bots.len() < MIN_PLAYERS || bots.len() > MAX_PLAYERS {
+        return Err(format!(
+            "--bots needs {} to {} seats, got {}",
+            MIN_PLAYERS,
+            MAX_PLAYERS,
+            bots.len()
+        ));
+    }
+    Ok(bots)
+}
+
+/// Plays every match, spreading them over the worker threads, and returns
+/// the results in seed order.
+fn run_all(options: &Options) -> Vec<MatchResult> {
+    let threads = options.threads as u64;
+    let mut results: Vec<(u64, MatchResult)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                scope.spawn(move || {
+                    (worker..options.games)
+                        .step_by(threads as usize)
+                        .map(|i| (i, run_match(options, options.seed.wrapping_add(i))))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn run_match(options: &Options, seed: u64) -> MatchResult {
+    let mut game = GameInstance::new(options.rules, seed);
+    for (id, difficulty) in options.bots.iter().enumerate() {
+        let name = format!("{:?} Bot {}", difficulty, id);
+        game.add_bot(id as u8, name, *difficulty);
+    }
+    game.start();
+    let mut replay = options
+        .record
+        .as_ref()
+        .map(|_| Replay::new(format!("sim-{}", seed), 0, &game));
+    while !game.finished && game.tick < options.max_ticks {
+        let inputs = game.tick();
+        if let (Some(replay), Some(inputs)) = (&mut replay, inputs) {
+            replay.record(inputs, &game);
+        }
+    }
+    if let (Some(dir), Some(mut replay)) = (&options.record, replay) {
+        replay.finish(&game);
+        if let Err(e) = save_replay(dir, &replay) {
+            eprintln!("sim: cannot save replay {}: {}", replay.id, e);
+        }
+    }
+    MatchResult {
+        winner: game.winner,
+        ticks: game.tick,
+        timed_out: !game.finished,
+        items: game.item_stats,
+    }
+}
+
+fn save_replay(dir: &Path, replay: &Replay) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    write_replay(&dir.join(format!("{}.json", replay.id)), replay)
+}
+
+fn write_replay(path: &Path, replay: &Replay) -> std::io::Result<()> {
+    std::fs::write(path, serde_json::to_vec(replay).unwrap())
+}
+
+/// Checks each replay file and returns the exit code: 0 if all match.
+fn verify(args: Vec<String>) -> i32 {
+    let bless = args.iter().any(|a| a == "--bless");
+    let files: Vec<&String> = args.iter().filter(|a| *a != "--bless").collect();
+    if files.is_empty() {
+        eprintln!("sim: verify needs at least one replay file\n\n{}", USAGE);
+        return 2;
+    }
+
+    let mut failed = 0;
+    for file in files {
+        let path = Path::new(file);
+        let mut replay: Replay = match std::fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()))
+        {
+            Ok(replay) => replay,
+            Err(e) => {
+                println!("FAIL {}: {}", file, e);
+                failed += 1;
+                continue;
+            }
+        };
+        match replay.verify() {
+            Ok(game) => println!(
+                "ok   {}: {} ticks, winner {:?}",
+                file, game.tick, game.winner
+            ),
+            Err(_) if bless => {
+                replay.bless();
+                match write_replay(path, &replay) {
+                    Ok(()) => println!("bless {}: recorded {} ticks", file, replay.ticks),
+                    Err(e) => {
+                        println!("FAIL {}: {}", file, e);
+                        failed += 1;
+                    }
+                }
+            }
+            Err(d) => {
+                failed += 1;
+                println!(
+                    "FAIL {}: diverged at tick {} (hash {:016x}, recorded {:016x})",
+                    file, d.tick, d.actual_hash, d.expected_hash
+                );
+                if let Some(tick) = d.checkpoint {
+                    println!("  state at tick {}:", tick);
+                }
+                for line in &d.diff {
+                    println!("  {}", line);
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+fn report(options: &Options, results: &[MatchResult]) {
+    let games = results.len().max(1) as f64;
+    let percent = |n: usize| 100.0 * n as f64 / games;
+
+    println!(
+        "{} matches, seeds {}..{}, rules {:?}",
+        results.len(),
+        options.seed,
+        options.seed.wrapping_add(options.games),
+        options.rules
+    );
+
+    println!("\nWins");
+    for (id, difficulty) in options.bots.iter().enumerate() {
+        let wins = results
+            .iter()
+            .filter(|r| r.winner == Some(id as u8))
+            .count();
+        println!(
+            "  seat {} {:<7} {:>6} {:>6.1}%",
+            id,
+            format!("{:?}", difficulty),
+            wins,
+            percent(wins)
+        );
+    }
+    let draws = results
+        .iter()
+        .filter(|r| r.winner.is_none() && !r.timed_out)
+        .count();
+    let timeouts = results.iter().filter(|r| r.timed_out).count();
+    println!("  draw           {:>6} {:>6.1}%", draws, percent(draws));
+    println!(
+        "  timed out      {:>6} {:>6.1}%",
+        timeouts,
+        percent(timeouts)
+    );
+
+    let finished: Vec<u64> = results
+        .iter()
+        .filter(|r| !r.timed_out)
+        .map(|r| r.ticks)
+        .collect();
+    println!("\nRound length (finished matches)");
+    if finished.is_empty() {
+        println!("  none finished within {} ticks", options.max_ticks);
+    } else {
+        let mean = finished.iter().sum::<u64>() as f64 / finished.len() as f64;
+        println!(
+            "  average {:.0} ticks ({:.1} s), shortest {}, longest {}",
+            mean,
+            mean * TICK_RATE_MS as f64 / 1000.0,
+            finished.iter().min().unwrap(),
+            finished.iter().max().unwrap()
+        );
+    }
+
+    let mut totals = ItemStats::default();
+    for r in results {
+        add_counts(&mut totals.dropped, &r.items.dropped);
+        add_counts(&mut totals.picked, &r.items.picked);
+        add_counts(&mut totals.burned, &r.items.burned);
+    }
+    println!("\nItems per match    dropped  picked  burned  picked%");
+    let row = |name: &str, pick: fn(&ItemCounts) -> u32| {
+        let dropped = pick(&totals.dropped);
+        let picked = pick(&totals.picked);
+        println!(
+            "  {:<14} {:>9.2} {:>7.2} {:>7.2} {:>7.1}%",
+            name,
+            dropped as f64 / games,
+            picked as f64 / games,
+            pick(&totals.burned) as f64 / games,
+            100.0 * picked as f64 / dropped.max(1) as f64
+        );
+    };
+    row("bomb range", |c| c.bomb_range);
+    row("bomb count", |c| c.bomb_count);
+    row("speed", |c| c.speed);
+    row("all", ItemCounts::total);
+}
+
+fn add_counts(total: &mut ItemCounts, counts: &ItemCounts) {
+    total.bomb_range += counts.bomb_range;
+    total.bomb_count += counts.bomb_count;
+    total.speed += counts.speed;
+}
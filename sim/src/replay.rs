@@ -0,0 +1,257 @@
+//! Recorded games: everything needed to play a game again tick for tick.
+
+use serde::{Deserialize, Serialize};
+use shared::map::GameMap;
+use shared::protocol::BotDifficulty;
+use shared::rules::Rules;
+
+use crate::game::{GameInstance, TickInputs};
+
+/// Bumped whenever a change to the format or the simulation would make old
+/// replays play out differently.
+pub const REPLAY_VERSION: u32 = 1;
+/// Ticks between the full state dumps kept for diffing a divergence.
+pub const CHECKPOINT_INTERVAL: u64 = 20;
+/// Ticks between state hashes in replays recorded by the live server, where
+/// hashing every tick would cost more than the tick itself.
+pub const LIVE_HASH_INTERVAL: u64 = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayPlayer {
+    pub id: u8,
+    pub name: String,
+    /// Spawn corner, which is also the player's colour.
+    pub spawn: u8,
+    /// Set for players a bot controlled from the start.
+    pub bot: Option<BotDifficulty>,
+}
+
+/// The recorded state at one tick, as `GameInstance::describe` lines.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub tick: u64,
+    pub state: Vec<String>,
+}
+
+/// Where a re-simulated replay stopped matching its recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// First hashed tick whose state differs. The game went wrong at most
+    /// `Replay::hash_interval` ticks before it.
+    pub tick: u64,
+    pub expected_hash: u64,
+    pub actual_hash: u64,
+    /// The first checkpoint at or after `tick`, and the lines that differ
+    /// there: `-` as recorded, `+` as re-simulated.
+    pub checkpoint: Option<u64>,
+    pub diff: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub id: String,
+    /// Seconds since the Unix epoch when the game started.
+    pub started_at: u64,
+    pub seed: u64,
+    pub rules: Rules,
+    pub map: GameMap,
+    pub players: Vec<ReplayPlayer>,
+    /// Inputs of every tick, in order.
+    pub inputs: Vec<TickInputs>,
+    /// `GameInstance::state_hash` after every `hash_interval` ticks, in order.
+    #[serde(default)]
+    pub hashes: Vec<u64>,
+    #[serde(default = "every_tick")]
+    pub hash_interval: u64,
+    /// Every `CHECKPOINT_INTERVAL` ticks, kept only when every tick is hashed.
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+    pub winner: Option<u8>,
+    pub ticks: u64,
+    /// `GameInstance::state_hash` after the last tick, as hex.
+    pub final_hash: String,
+}
+
+impl Replay {
+    /// Starts recording a game that has been set up but not yet ticked.
+    pub fn new(id: String, started_at: u64, game: &GameInstance) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            id,
+            started_at,
+            seed: game.seed,
+            rules: game.rules,
+            map: game.map,
+            players: game
+                .players
+                .iter()
+                .map(|p| ReplayPlayer {
+                    id: p.id,
+                    name: p.name.clone(),
+                    spawn: p.color_index,
+                    bot: game.bot_difficulty(p.id),
+                })
+                .collect(),
+            inputs: Vec::new(),
+            hashes: Vec::new(),
+            hash_interval: 1,
+            checkpoints: Vec::new(),
+            winner: None,
+            ticks: 0,
+            final_hash: String::new(),
+        }
+    }
+
+    /// Hashes the state only every `interval` ticks and keeps no
+    /// checkpoints, trading how closely `verify` can place a divergence for
+    /// recording cheaply enough to run alongside a live game.
+    pub fn with_hash_interval(mut self, interval: u64) -> Self {
+        self.hash_interval = interval.max(1);
+        self
+    }
+
+    /// Records a tick's inputs along with the state `game` reached.
+    pub fn record(&mut self, inputs: TickInputs, game: &GameInstance) {
+        self.inputs.push(inputs);
+        if !game.tick.is_multiple_of(self.hash_interval) {
+            return;
+        }
+        self.hashes.push(game.state_hash());
+        if self.hash_interval == 1 && game.tick.is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.checkpoints.push(Checkpoint {
+                tick: game.tick,
+                state: game.describe(),
+            });
+        }
+    }
+
+    /// Notes how the game ended.
+    pub fn finish(&mut self, game: &GameInstance) {
+        self.winner = game.winner;
+        self.ticks = game.tick;
+        self.final_hash = format!("{:016x}", game.state_hash());
+    }
+
+    /// A started game in the recorded starting position, with every player
+    /// left to the recorded inputs, bots included.
+    pub fn initial_game(&self) -> GameInstance {
+        let mut game = GameInstance::new(self.rules, self.seed);
+        game.map = self.map;
+        for p in &self.players {
+            game.add_player_at(p.id, p.name.clone(), p.spawn as usize);
+        }
+        game.start();
+        game
+    }
+
+    /// Plays every recorded tick and returns the final game.
+    pub fn play(&self) -> GameInstance {
+        let mut game = self.initial_game();
+        for inputs in &self.inputs {
+            game.step(inputs);
+        }
+        game
+    }
+
+    /// Re-simulates the replay, checking the state after every hashed tick
+    /// against the recording, and returns the final game if all of it
+    /// matches.
+    pub fn verify(&self) -> Result<GameInstance, Divergence> {
+        let mut game = self.initial_game();
+        let interval = self.hash_interval.max(1);
+        for (i, inputs) in self.inputs.iter().enumerate() {
+            game.step(inputs);
+            if !game.tick.is_multiple_of(interval) {
+                continue;
+            }
+            let hashed = (game.tick / interval) as usize - 1;
+            let Some(&expected_hash) = self.hashes.get(hashed) else {
+                continue;
+            };
+            let actual_hash = game.state_hash();
+            if actual_hash != expected_hash {
+                return Err(self.divergence(game, i, expected_hash, actual_hash));
+            }
+        }
+
+        let actual_hash = game.state_hash();
+        if format!("{:016x}", actual_hash) != self.final_hash
+            || game.winner != self.winner
+            || game.tick != self.ticks
+        {
+            let expected_hash = u64::from_str_radix(&self.final_hash, 16).unwrap_or(0);
+            return Err(Divergence {
+                tick: game.tick,
+                expected_hash,
+                actual_hash,
+                checkpoint: None,
+                diff: vec![
+                    format!("- ticks={} winner={:?}", self.ticks, self.winner),
+                    format!("+ ticks={} winner={:?}", game.tick, game.winner),
+                ],
+            });
+        }
+        Ok(game)
+    }
+
+    // Plays on from the first bad tick to the next checkpoint and diffs it.
+    fn divergence(
+        &self,
+        mut game: GameInstance,
+        index: usize,
+        expected_hash: u64,
+        actual_hash: u64,
+    ) -> Divergence {
+        let tick = game.tick;
+        let checkpoint = self.checkpoints.iter().find(|c| c.tick >= tick);
+        let mut diff = Vec::new();
+        if let Some(checkpoint) = checkpoint {
+            for inputs in &self.inputs[index + 1..] {
+                if game.tick >= checkpoint.tick {
+                    break;
+                }
+                game.step(inputs);
+            }
+            let actual = game.describe();
+            diff.extend(
+                checkpoint
+                    .state
+                    .iter()
+                    .filter(|line| !actual.contains(line))
+                    .map(|line| format!("- {}", line)),
+            );
+            diff.extend(
+                actual
+                    .iter()
+                    .filter(|line| !checkpoint.state.contains(line))
+                    .map(|line| format!("+ {}", line)),
+            );
+        }
+        Divergence {
+            tick,
+            expected_hash,
+            actual_hash,
+            checkpoint: checkpoint.map(|c| c.tick),
+            diff,
+        }
+    }
+
+    /// Re-records the expected results from the current simulation, for when
+    /// a gameplay change is meant to alter how this replay plays out.
+    pub fn bless(&mut self) {
+        let mut game = self.initial_game();
+        let inputs = std::mem::take(&mut self.inputs);
+        self.hashes.clear();
+        self.checkpoints.clear();
+        for tick_inputs in inputs {
+            game.step(&tick_inputs);
+            self.record(tick_inputs, &game);
+        }
+        self.finish(&game);
+    }
+}
+
+fn every_tick() -> u64 {
+    1
+}
@@ -0,0 +1,65 @@
+//! Bot difficulties measured against each other in full games.
+
+use shared::protocol::BotDifficulty;
+use shared::rules::Rules;
+use sim::game::GameInstance;
+
+/// Plays `a` in seat 0 against `b` in seat 1 and returns the winning seat.
+fn play(a: BotDifficulty, b: BotDifficulty, seed: u64) -> Option<u8> {
+    let mut game = GameInstance::new(Rules::default(), seed);
+    game.add_bot(0, "a".into(), a);
+    game.add_bot(1, "b".into(), b);
+    game.start();
+    while !game.finished && game.tick < 3000 {
+        game.tick();
+    }
+    game.winner
+}
+
+/// Wins for `strong` and `weak` over `games` seeds, swapping seats each game.
+fn score(strong: BotDifficulty, weak: BotDifficulty, games: u64) -> (u32, u32) {
+    let (mut wins, mut losses) = (0, 0);
+    for seed in 0..games {
+        let swapped = seed % 2 == 1;
+        let winner = if swapped {
+            play(weak, strong, seed).map(|seat| 1 - seat)
+        } else {
+            play(strong, weak, seed)
+        };
+        match winner {
+            Some(0) => wins += 1,
+            Some(_) => losses += 1,
+            None => {}
+        }
+    }
+    (wins, losses)
+}
+
+#[test]
+fn hard_beats_medium() {
+    let (wins, losses) = score(BotDifficulty::Hard, BotDifficulty::Medium, 20);
+    assert!(wins > 2 * losses, "hard won {wins}, medium won {losses}");
+}
+
+#[test]
+fn medium_beats_easy() {
+    let (wins, losses) = score(BotDifficulty::Medium, BotDifficulty::Easy, 20);
+    assert!(wins > 2 * losses, "medium won {wins}, easy won {losses}");
+}
+
+#[test]
+fn hard_games_depend_on_the_seed() {
+    let history = |seed| {
+        let mut game = GameInstance::new(Rules::default(), seed);
+        game.add_bot(0, "a".into(), BotDifficulty::Hard);
+        game.add_bot(1, "b".into(), BotDifficulty::Hard);
+        game.start();
+        let mut hashes = Vec::new();
+        while !game.finished && game.tick < 600 {
+            game.tick();
+            hashes.push(game.state_hash());
+        }
+        hashes
+    };
+    assert_ne!(history(1), history(2));
+}
@@ -0,0 +1,133 @@
+//! The training environment's step, reward and episode-end behaviour.
+
+use shared::constants::*;
+use shared::protocol::{BotAction, BotDifficulty};
+use sim::env::{channel, Controller, Env, EnvConfig, Observation, Rewards};
+
+fn two_agents(max_ticks: u64) -> EnvConfig {
+    EnvConfig {
+        seats: vec![Controller::Agent, Controller::Agent],
+        max_ticks,
+        ..EnvConfig::default()
+    }
+}
+
+#[test]
+fn reset_gives_a_full_observation_at_tick_zero() {
+    let mut env = Env::new(EnvConfig::default());
+    let obs = env.reset(1);
+    assert_eq!(obs.tick, 0);
+    assert_eq!(obs.grid.len(), channel::COUNT * MAP_ROWS * MAP_COLS);
+    assert!(obs.seats.iter().all(|seat| seat.alive));
+    for (seat, features) in obs.seats.iter().enumerate() {
+        let (x, y) = (features.x as usize, features.y as usize);
+        assert_eq!(obs.get(channel::PLAYER + seat, x, y), 1.0);
+    }
+    assert!(!env.is_done());
+}
+
+#[test]
+fn step_advances_one_tick_and_rewards_every_seat() {
+    let mut env = Env::new(two_agents(100));
+    env.reset(1);
+    for tick in 1..=10 {
+        let (obs, rewards, done) = env.step(&[BotAction::Stay, BotAction::Stay]);
+        assert_eq!(obs.tick, tick);
+        assert_eq!(rewards, vec![0.0, 0.0]);
+        assert!(!done);
+    }
+}
+
+#[test]
+fn running_out_of_time_is_a_draw() {
+    let mut config = two_agents(5);
+    config.rewards = Rewards {
+        alive: 0.01,
+        draw: 0.5,
+        ..Rewards::default()
+    };
+    let mut env = Env::new(config);
+    env.reset(1);
+    for _ in 0..4 {
+        let (_, rewards, done) = env.step(&[]);
+        assert_eq!(rewards, vec![0.01, 0.01]);
+        assert!(!done);
+    }
+    let (obs, rewards, done) = env.step(&[]);
+    assert_eq!(obs.tick, 5);
+    assert_eq!(rewards, vec![0.51, 0.51]);
+    assert!(done);
+
+    // Stepping a finished episode changes nothing.
+    let (again, rewards, done) = env.step(&[BotAction::Up, BotAction::Up]);
+    assert_eq!(again, obs);
+    assert_eq!(rewards, vec![0.0, 0.0]);
+    assert!(done);
+}
+
+#[test]
+fn bombing_yourself_loses_to_the_other_seat() {
+    let mut env = Env::new(two_agents(1000));
+    env.reset(1);
+    // Wait out spawn protection so the blast counts.
+    for _ in 0..SPAWN_INVULNERABILITY_TICKS {
+        env.step(&[]);
+    }
+    let (obs, _, _) = env.step(&[BotAction::Bomb, BotAction::Stay]);
+    assert!(!obs.seats[0].invulnerable);
+
+    let mut total = [0.0; 2];
+    let mut done = false;
+    for _ in 0..BOMB_TIMER_TICKS + EXPLOSION_DURATION_TICKS {
+        let (_, rewards, finished) = env.step(&[]);
+        total[0] += rewards[0];
+        total[1] += rewards[1];
+        if finished {
+            done = true;
+            break;
+        }
+    }
+    assert!(done);
+    assert_eq!(env.game().winner, Some(1));
+    let defaults = Rewards::default();
+    assert_eq!(total, [defaults.death, defaults.win]);
+}
+
+#[test]
+fn a_seed_replays_the_same_episode() {
+    let config = EnvConfig {
+        seats: vec![
+            Controller::Agent,
+            Controller::Bot(BotDifficulty::Easy),
+            Controller::Bot(BotDifficulty::Medium),
+            Controller::Bot(BotDifficulty::Hard),
+        ],
+        max_ticks: 600,
+        ..EnvConfig::default()
+    };
+    let run = |seed: u64| -> Vec<(Observation, Vec<f32>, bool)> {
+        let mut env = Env::new(config.clone());
+        env.reset(seed);
+        let actions = [
+            BotAction::Up,
+            BotAction::Right,
+            BotAction::Bomb,
+            BotAction::Down,
+            BotAction::Left,
+            BotAction::Stay,
+        ];
+        let mut steps = Vec::new();
+        for i in 0..config.max_ticks as usize {
+            let step = env.step(&[actions[i / 7 % actions.len()]]);
+            let done = step.2;
+            steps.push(step);
+            if done {
+                break;
+            }
+        }
+        steps
+    };
+    let first = run(42);
+    assert!(first.len() > 1);
+    assert!(first == run(42), "seed 42 played out differently");
+}
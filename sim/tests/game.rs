@@ -0,0 +1,29 @@
+//! Game rules that bots and players both depend on.
+
+use shared::constants::*;
+use shared::rules::Rules;
+use sim::game::GameInstance;
+
+#[test]
+fn a_blast_sets_off_bombs_it_reaches() {
+    let mut game = GameInstance::new(Rules::default(), 1);
+    game.add_player(0, "a".into());
+    game.add_player(1, "b".into());
+    game.start();
+    game.players[0].max_bombs = 2;
+
+    // Two bombs side by side, the second dropped a tick after the first.
+    game.queue_bomb(0);
+    game.tick();
+    game.players[0].x += SUBTILE;
+    game.queue_bomb(0);
+    game.tick();
+    assert_eq!(game.players[0].active_bombs, 2);
+
+    for _ in 2..BOMB_TIMER_TICKS - 1 {
+        game.tick();
+    }
+    assert_eq!(game.players[0].active_bombs, 2);
+    game.tick();
+    assert_eq!(game.players[0].active_bombs, 0, "both bombs went off");
+}
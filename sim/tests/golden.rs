@@ -0,0 +1,52 @@
+//! Golden replays must play out exactly as recorded. If a gameplay change is
+//! meant to alter them, re-record their results with
+//! `cargo run -p sim -- verify --bless sim/tests/golden/*.json`.
+
+use std::path::Path;
+
+use sim::replay::Replay;
+
+#[test]
+fn golden_replays_match() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no replays in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for path in files {
+        let replay: Replay = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        if let Err(d) = replay.verify() {
+            failures.push(format!(
+                "{} diverged at tick {}, state at tick {:?}:\n{}",
+                path.display(),
+                d.tick,
+                d.checkpoint,
+                d.diff.join("\n")
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn sparse_hashes_still_catch_a_divergence() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/four-bots-timeout.json");
+    let golden: Replay = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+    let mut replay = golden.with_hash_interval(20);
+    replay.bless();
+    assert_eq!(replay.hashes.len() as u64, replay.ticks / 20);
+    assert!(replay.checkpoints.is_empty());
+    assert!(replay.verify().is_ok());
+
+    // Knock a player out on tick 45; the next hash is at tick 60.
+    replay.inputs[44].inputs[0].forfeit = true;
+    let Err(divergence) = replay.verify() else {
+        panic!("a forfeit went unnoticed");
+    };
+    assert_eq!(divergence.tick, 60);
+}